@@ -0,0 +1,186 @@
+//! Game actions and the buttons bound to them.
+
+use std::collections::BTreeMap;
+
+use piston::input::{Button, Key};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    /// Moves the second paddle in co-op.
+    P2MoveLeft,
+    P2MoveRight,
+    QuickSave,
+    QuickLoad,
+    /// Rewinds the game while held.
+    Rewind,
+    Pause,
+    /// Runs a single tick while paused.
+    Step,
+    /// Asks for a new button for every action in turn.
+    Rebind,
+}
+
+impl Action {
+    pub const ALL: [Action; 10] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::P2MoveLeft,
+        Action::P2MoveRight,
+        Action::QuickSave,
+        Action::QuickLoad,
+        Action::Rewind,
+        Action::Pause,
+        Action::Step,
+        Action::Rebind,
+    ];
+
+    /// Name as used in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::P2MoveLeft => "p2_move_left",
+            Action::P2MoveRight => "p2_move_right",
+            Action::QuickSave => "quick_save",
+            Action::QuickLoad => "quick_load",
+            Action::Rewind => "rewind",
+            Action::Pause => "pause",
+            Action::Step => "step",
+            Action::Rebind => "rebind",
+        }
+    }
+}
+
+/// Maps keys and mouse buttons to actions, an action can have any number of buttons.
+/// Deserializing only overrides the actions that are listed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(from = "BTreeMap<Action, Vec<Button>>", into = "BTreeMap<Action, Vec<Button>>")]
+pub struct Bindings {
+    table: BTreeMap<Action, Vec<Button>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let key = |key| vec![Button::Keyboard(key)];
+        let table = Action::ALL
+            .iter()
+            .map(|&action| {
+                let buttons = match action {
+                    Action::MoveLeft => key(Key::Left),
+                    Action::MoveRight => key(Key::Right),
+                    Action::P2MoveLeft => key(Key::A),
+                    Action::P2MoveRight => key(Key::D),
+                    Action::QuickSave => key(Key::F5),
+                    Action::QuickLoad => key(Key::F9),
+                    Action::Rewind => key(Key::Backspace),
+                    Action::Pause => key(Key::P),
+                    Action::Step => key(Key::Period),
+                    Action::Rebind => key(Key::F2),
+                };
+                (action, buttons)
+            })
+            .collect();
+        Self { table }
+    }
+}
+
+impl From<BTreeMap<Action, Vec<Button>>> for Bindings {
+    fn from(table: BTreeMap<Action, Vec<Button>>) -> Self {
+        let mut bindings = Self::default();
+        bindings.table.extend(table);
+        bindings
+    }
+}
+
+impl From<Bindings> for BTreeMap<Action, Vec<Button>> {
+    fn from(bindings: Bindings) -> Self {
+        bindings.table
+    }
+}
+
+impl Bindings {
+    pub fn action(&self, button: Button) -> Option<Action> {
+        self.table.iter().find(|(_, buttons)| buttons.contains(&button)).map(|(&action, _)| action)
+    }
+
+    pub fn buttons(&self, action: Action) -> &[Button] {
+        self.table.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Makes `button` the only button of `action`, taking it away from any other action.
+    pub fn bind(&mut self, action: Action, button: Button) {
+        for buttons in self.table.values_mut() {
+            buttons.retain(|&b| b != button);
+        }
+        self.table.insert(action, vec![button]);
+    }
+
+    /// A button bound to two actions, with both actions.
+    pub fn conflict(&self) -> Option<(Button, Action, Action)> {
+        for (&first, buttons) in &self.table {
+            for &button in buttons {
+                if let Some((&second, _)) = self.table.range(first..).skip(1).find(|(_, b)| b.contains(&button)) {
+                    return Some((button, first, second));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboard::{Direction, Gameboard};
+    use crate::gameboard_controller::GameboardController;
+    use piston::input::MouseButton;
+
+    #[test]
+    fn test_overlapping_movement() {
+        let mut controller = GameboardController::new(Gameboard::new(400.));
+        let direction = |c: &GameboardController| c.gameboard.players[0].direction;
+
+        controller.handle_action(Action::MoveLeft, true);
+        controller.handle_action(Action::MoveRight, true);
+        assert!(direction(&controller) == Direction::Right);
+        controller.handle_action(Action::MoveRight, false);
+        assert!(direction(&controller) == Direction::Left);
+
+        controller.handle_action(Action::MoveRight, true);
+        controller.handle_action(Action::MoveLeft, false);
+        assert!(direction(&controller) == Direction::Right);
+        controller.handle_action(Action::MoveRight, false);
+        assert!(direction(&controller) == Direction::Idle);
+    }
+
+    #[test]
+    fn test_bind_moves_button() {
+        let mut bindings = Bindings::default();
+        let click = Button::Mouse(MouseButton::Left);
+
+        bindings.bind(Action::MoveLeft, click);
+        assert_eq!(bindings.action(click), Some(Action::MoveLeft));
+        assert_eq!(bindings.action(Button::Keyboard(Key::Left)), None);
+
+        bindings.bind(Action::MoveRight, click);
+        assert_eq!(bindings.action(click), Some(Action::MoveRight));
+        assert!(bindings.buttons(Action::MoveLeft).is_empty());
+        assert_eq!(bindings.conflict(), None);
+    }
+
+    #[test]
+    fn test_conflict() {
+        let mut table = BTreeMap::new();
+        table.insert(Action::Rewind, vec![Button::Keyboard(Key::Left)]);
+        let bindings = Bindings::from(table);
+
+        assert_eq!(
+            bindings.conflict(),
+            Some((Button::Keyboard(Key::Left), Action::MoveLeft, Action::Rewind))
+        );
+    }
+}
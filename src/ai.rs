@@ -0,0 +1,190 @@
+//! Computer controlled paddle for demos and automated play.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::gameboard::{Block, Facing, Gameboard};
+use crate::math::Rng;
+use crate::replay::Input;
+
+/// Largest sideways part of the bounce the AI asks for, steeper bounces are easy to miss.
+const MAX_AIM: f64 = 0.8;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AiSettings {
+    /// Ticks between the AI seeing the ball and the paddle reacting.
+    pub reaction_delay: u32,
+    /// Largest distance the AI misjudges where the ball lands by, in board units.
+    pub error: f64,
+    /// Hit the ball with the part of the paddle that sends it towards the nearest block.
+    pub aim: bool,
+}
+
+impl Default for AiSettings {
+    fn default() -> Self {
+        Self {
+            reaction_delay: 12,
+            error: 8.,
+            aim: true,
+        }
+    }
+}
+
+/// Where the center of the ball crosses the line the paddle with index `player` moves on,
+/// following reflections on the side walls. None while the ball moves away from the paddle.
+pub fn predict_crossing(board: &Gameboard, player: usize) -> Option<f64> {
+    let paddle = &board.players.get(player)?.body.hitbox;
+    let ball = &board.ball.body;
+    let half = ball.hitbox.dimension.x / 2.;
+    let center = ball.hitbox.position + ball.hitbox.dimension / 2.;
+
+    // Distance the ball's center travels until its edge touches the paddle
+    let distance = match board.players[player].facing {
+        Facing::Up => paddle.position.y - (center.y + half),
+        Facing::Down => (center.y - half) - (paddle.position.y + paddle.dimension.y),
+    };
+    let towards = match board.players[player].facing {
+        Facing::Up => ball.velocity.y > 0.,
+        Facing::Down => ball.velocity.y < 0.,
+    };
+    if !towards {
+        return None;
+    }
+
+    let x = center.x + ball.velocity.x * distance.max(0.) / ball.velocity.y.abs();
+
+    // Unfold the reflections between the side walls
+    let low = half;
+    let width = board.size - 2. * half;
+    if width <= 0. {
+        return Some(board.size / 2.);
+    }
+    let unfolded = (x - low).rem_euclid(2. * width);
+    Some(low + if unfolded > width { 2. * width - unfolded } else { unfolded })
+}
+
+/// The destructible block closest to the paddle's side of the board.
+fn target_block(board: &Gameboard, facing: Facing) -> Option<&Block> {
+    let y = |block: &&Block| block.body.hitbox.position.y;
+    let blocks = board.blocks.iter().filter(|b| b.is_destructible());
+    match facing {
+        Facing::Up => blocks.max_by(|a, b| y(a).total_cmp(&y(b))),
+        Facing::Down => blocks.min_by(|a, b| y(a).total_cmp(&y(b))),
+    }
+}
+
+/// Steers the first paddle by sending the same inputs as the mouse would.
+pub struct Ai {
+    pub settings: AiSettings,
+    rng: Rng,
+    /// Decided paddle targets with the tick they take effect at.
+    pending: VecDeque<(u64, f64)>,
+    /// Ball velocity the current guess was made for, a bounce makes the AI guess again.
+    seen_velocity: Option<(f64, f64)>,
+    misjudgement: f64,
+    last_target: Option<f64>,
+}
+
+impl Ai {
+    pub fn new(settings: AiSettings, seed: u64) -> Self {
+        Self {
+            settings,
+            rng: Rng::new(seed),
+            pending: VecDeque::new(),
+            seen_velocity: None,
+            misjudgement: 0.,
+            last_target: None,
+        }
+    }
+
+    /// Looks at the board before the tick `tick` runs, returns the input to apply if the paddle should move elsewhere.
+    pub fn think(&mut self, board: &Gameboard, tick: u64) -> Option<Input> {
+        let velocity = board.ball.body.velocity;
+        if self.seen_velocity != Some((velocity.x, velocity.y)) {
+            self.seen_velocity = Some((velocity.x, velocity.y));
+            self.misjudgement = (self.rng.next_f64() * 2. - 1.) * self.settings.error;
+        }
+
+        let target = self.decide(board);
+        self.pending.push_back((tick + u64::from(self.settings.reaction_delay), target));
+
+        let mut due = None;
+        while let Some(&(at, target)) = self.pending.front() {
+            if at > tick {
+                break;
+            }
+            due = Some(target);
+            self.pending.pop_front();
+        }
+
+        let target = due.filter(|&t| self.last_target != Some(t))?;
+        self.last_target = Some(target);
+        Some(Input::PaddleTarget(target))
+    }
+
+    /// Where the paddle's center should be to meet the ball, or the middle of the board while the ball is away.
+    fn decide(&self, board: &Gameboard) -> f64 {
+        let player = &board.players[0];
+        let crossing = match predict_crossing(board, 0) {
+            Some(x) => x + self.misjudgement,
+            None => return board.size / 2.,
+        };
+        if !self.settings.aim {
+            return crossing;
+        }
+
+        // The ball leaves at (offset, ±1) where offset is where it hits the paddle, -1 left edge to 1 right edge
+        let half_width = player.body.hitbox.dimension.x / 2.;
+        let offset = target_block(board, player.facing).map_or(0., |block| {
+            let target = block.body.hitbox.position + block.body.hitbox.dimension / 2.;
+            let line = player.body.hitbox.position.y;
+            (target.x - crossing) / (line - target.y).abs().max(1.)
+        });
+        crossing - offset.clamp(-MAX_AIM, MAX_AIM) * half_width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboard::PhysicsSettings;
+    use crate::gameboard_controller::GameboardController;
+    use crate::math::Vec2;
+
+    #[test]
+    fn test_prediction_follows_reflections() {
+        let mut board = Gameboard::with_blocks(400., Vec::new());
+        // The ball's center starts at (170, 255), 90 above where its edge meets the paddle
+        board.ball.body.velocity = Vec2::new(1., 1.);
+        assert!((predict_crossing(&board, 0).unwrap() - 260.).abs() < 1e-9);
+
+        // 170 + 270 = 440 is 45 past where the right wall stops the center
+        board.ball.body.velocity = Vec2::new(300., 100.);
+        assert!((predict_crossing(&board, 0).unwrap() - 350.).abs() < 1e-9);
+
+        board.ball.body.velocity = Vec2::new(-3., 1.);
+        assert!((predict_crossing(&board, 0).unwrap() - 110.).abs() < 1e-9);
+
+        board.ball.body.velocity = Vec2::new(1., -1.);
+        assert_eq!(predict_crossing(&board, 0), None);
+    }
+
+    #[test]
+    fn test_ai_keeps_ball_in_play() {
+        let board = Gameboard::with_physics(400., crate::gameboard::default_blocks(), PhysicsSettings::default());
+        let mut controller = GameboardController::new(board);
+        let mut ai = Ai::new(AiSettings::default(), 7);
+
+        for _ in 0..120 * 60 {
+            if let Some(input) = ai.think(&controller.gameboard, controller.tick) {
+                controller.handle_input(input);
+            }
+            controller.step(1. / 120.);
+        }
+
+        assert_eq!(controller.gameboard.lives, crate::gameboard::STARTING_LIVES);
+        assert!(controller.gameboard.score > 0);
+    }
+}
@@ -0,0 +1,219 @@
+//! Stepping many learning environments at once, spread over the CPU cores.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use crate::gameboard::Direction;
+use crate::gym::{EnvSettings, Environment, Observation};
+
+const WORKER_PANICKED: &str = "Simulation thread panicked";
+
+/// An environment and whether its episode is over.
+struct Slot {
+    env: Environment,
+    done: bool,
+}
+
+impl Slot {
+    /// Finished environments are left as they are and report their last observation with no reward.
+    fn step(&mut self, action: Direction) -> (Observation, f64, bool) {
+        if self.done {
+            return (self.env.observe(), 0., true);
+        }
+        let result = self.env.step(action);
+        self.done = result.2;
+        result
+    }
+}
+
+/// Work for a worker, with one seed or action per environment it owns.
+enum Job {
+    Reset(Vec<u64>),
+    Step(Vec<Direction>),
+    Features,
+}
+
+enum Reply {
+    Observations(Vec<Observation>),
+    Steps(Vec<(Observation, f64, bool)>),
+    Features(Vec<Vec<f32>>),
+}
+
+/// A thread owning a contiguous chunk of the environments for as long as the batch lives.
+struct Worker {
+    count: usize,
+    jobs: Sender<Job>,
+    replies: Receiver<Reply>,
+    handle: JoinHandle<()>,
+}
+
+fn work(mut slots: Vec<Slot>, jobs: Receiver<Job>, replies: Sender<Reply>) {
+    // Ends once the batch is dropped and closes the job channel
+    for job in jobs {
+        let reply = match job {
+            Job::Reset(seeds) => Reply::Observations(
+                slots
+                    .iter_mut()
+                    .zip(seeds)
+                    .map(|(slot, seed)| {
+                        slot.done = false;
+                        slot.env.reset(seed)
+                    })
+                    .collect(),
+            ),
+            Job::Step(actions) => Reply::Steps(slots.iter_mut().zip(actions).map(|(slot, action)| slot.step(action)).collect()),
+            Job::Features => Reply::Features(slots.iter().map(|slot| slot.env.features()).collect()),
+        };
+        if replies.send(reply).is_err() {
+            return;
+        }
+    }
+}
+
+/// A fixed number of independent environments. Every environment only depends on its own seed and actions,
+/// so the results are the same for any number of threads.
+pub struct BatchEnv {
+    workers: Vec<Worker>,
+    count: usize,
+}
+
+impl BatchEnv {
+    /// Creates `count` environments, stepped by `threads` threads or one per core if None.
+    /// The threads are started here and kept until the batch is dropped.
+    pub fn new(settings: &EnvSettings, count: usize, threads: Option<usize>) -> Self {
+        let threads = threads.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+        let chunk = count.div_ceil(threads.max(1)).max(1);
+
+        let workers = (0..count)
+            .step_by(chunk)
+            .map(|start| {
+                let chunk_count = chunk.min(count - start);
+                let settings = settings.clone();
+                let (jobs, job_receiver) = mpsc::channel();
+                let (reply_sender, replies) = mpsc::channel();
+                let handle = thread::spawn(move || {
+                    let slots = (0..chunk_count)
+                        .map(|_| Slot {
+                            env: Environment::new(settings.clone()),
+                            done: false,
+                        })
+                        .collect();
+                    work(slots, job_receiver, reply_sender);
+                });
+                Worker {
+                    count: chunk_count,
+                    jobs,
+                    replies,
+                    handle,
+                }
+            })
+            .collect();
+
+        Self { workers, count }
+    }
+
+    /// Sends every worker its job, then waits for all replies in worker order.
+    fn request(&self, jobs: Vec<Job>) -> Vec<Reply> {
+        for (worker, job) in self.workers.iter().zip(jobs) {
+            worker.jobs.send(job).expect(WORKER_PANICKED);
+        }
+        self.workers.iter().map(|worker| worker.replies.recv().expect(WORKER_PANICKED)).collect()
+    }
+
+    /// Splits one value per environment into the chunks of the workers.
+    fn scatter<T: Copy>(&self, values: &[T]) -> Vec<Vec<T>> {
+        let mut values = values.iter().copied();
+        self.workers.iter().map(|worker| values.by_ref().take(worker.count).collect()).collect()
+    }
+
+    /// Starts a new episode in every environment, `seeds` has one seed per environment.
+    pub fn reset(&mut self, seeds: &[u64]) -> Vec<Observation> {
+        assert_eq!(seeds.len(), self.count, "one seed per environment");
+        let jobs = self.scatter(seeds).into_iter().map(Job::Reset).collect();
+        self.request(jobs)
+            .into_iter()
+            .flat_map(|reply| match reply {
+                Reply::Observations(observations) => observations,
+                _ => unreachable!("workers answer every job with its own reply"),
+            })
+            .collect()
+    }
+
+    /// Steps every environment with its action. Finished environments are left as they are
+    /// and report their last observation with no reward until they are reset.
+    pub fn step(&mut self, actions: &[Direction]) -> Vec<(Observation, f64, bool)> {
+        assert_eq!(actions.len(), self.count, "one action per environment");
+        let jobs = self.scatter(actions).into_iter().map(Job::Step).collect();
+        self.request(jobs)
+            .into_iter()
+            .flat_map(|reply| match reply {
+                Reply::Steps(results) => results,
+                _ => unreachable!("workers answer every job with its own reply"),
+            })
+            .collect()
+    }
+
+    /// Feature vectors of every environment, whatever kind of observation they return.
+    pub fn features(&self) -> Vec<Vec<f32>> {
+        let jobs = self.workers.iter().map(|_| Job::Features).collect();
+        self.request(jobs)
+            .into_iter()
+            .flat_map(|reply| match reply {
+                Reply::Features(features) => features,
+                _ => unreachable!("workers answer every job with its own reply"),
+            })
+            .collect()
+    }
+}
+
+impl Drop for BatchEnv {
+    fn drop(&mut self) {
+        for worker in self.workers.drain(..) {
+            // Closing the job channel stops the worker, a panic was already reported by the thread
+            drop(worker.jobs);
+            let _ = worker.handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gym::follow_ball;
+
+    /// Plays every environment with the baseline until all are done, returns the total rewards.
+    fn play(batch: &mut BatchEnv, seeds: &[u64]) -> Vec<f64> {
+        let settings = EnvSettings::default();
+        let width = (settings.physics.paddle_size.x / settings.size) as f32;
+        batch.reset(seeds);
+        let mut totals = vec![0.; seeds.len()];
+        loop {
+            let actions: Vec<_> = batch.features().iter().map(|f| follow_ball(f, width)).collect();
+            let results = batch.step(&actions);
+            for (total, (_, reward, _)) in totals.iter_mut().zip(&results) {
+                *total += reward;
+            }
+            if results.iter().all(|r| r.2) {
+                return totals;
+            }
+        }
+    }
+
+    #[test]
+    fn test_results_do_not_depend_on_threads() {
+        let settings = EnvSettings {
+            max_steps: Some(300),
+            ..EnvSettings::default()
+        };
+        let seeds: Vec<u64> = (0..9).map(|i| i % 4).collect();
+
+        let single = play(&mut BatchEnv::new(&settings, seeds.len(), Some(1)), &seeds);
+        let parallel = play(&mut BatchEnv::new(&settings, seeds.len(), Some(4)), &seeds);
+        assert_eq!(single, parallel);
+
+        // Environments with the same seed play the same game
+        assert_eq!(single[0], single[4]);
+        assert_eq!(single[1], single[5]);
+        assert_ne!(single[0], single[1]);
+    }
+}
@@ -0,0 +1,372 @@
+//! Playing through a line based JSON protocol, so bots can be written in any language.
+//!
+//! The game opens with a `hello` message describing the board and waits for the bot to answer `ready`.
+//! Then it sends a `state` message before every tick and applies the commands the bot sent. In lockstep
+//! the game waits for exactly one command per tick, otherwise it runs in real time with whatever arrived.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::gameboard::{BlockKind, Direction, Rectangle};
+use crate::gameboard_controller::GameboardController;
+use crate::headless::{Outcome, Summary, DT};
+use crate::replay::Input;
+
+/// Bumped whenever the messages change shape.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// How long the game waits for the bot to answer the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum BotError {
+    Io(io::Error),
+    /// The bot sent something that is not a valid command.
+    Protocol(String),
+    Disconnected,
+}
+
+impl fmt::Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BotError::Io(e) => write!(f, "could not talk to the bot: {}", e),
+            BotError::Protocol(reason) => write!(f, "bot broke the protocol: {}", reason),
+            BotError::Disconnected => write!(f, "bot disconnected"),
+        }
+    }
+}
+
+impl From<io::Error> for BotError {
+    fn from(e: io::Error) -> Self {
+        BotError::Io(e)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Move {
+    Left,
+    Right,
+    Idle,
+}
+
+/// Messages from the bot.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Command {
+    /// Answer to `hello`.
+    Ready {
+        #[serde(default)]
+        name: Option<String>,
+    },
+    /// Holds the paddle's movement keys.
+    Move { direction: Move },
+    /// Moves the paddle's center towards a board x coordinate, like the mouse.
+    Target { x: f64 },
+    /// Keeps doing the same, for lockstep where every tick needs a command.
+    Wait,
+    Quit,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    fn of(hitbox: &Rectangle) -> Self {
+        Self {
+            x: hitbox.position.x,
+            y: hitbox.position.y,
+            width: hitbox.dimension.x,
+            height: hitbox.dimension.y,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BlockState {
+    #[serde(flatten)]
+    pub rect: Rect,
+    /// Hits left, None for indestructible blocks.
+    pub hits: Option<u32>,
+}
+
+/// Messages from the game.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    Hello {
+        version: u32,
+        /// The board is a square with the origin in the top left corner.
+        board_size: f64,
+        paddle_size: [f64; 2],
+        ball_size: f64,
+        /// Seconds simulated per tick.
+        dt: f64,
+        lockstep: bool,
+        /// The bot controls the first of these.
+        paddles: usize,
+    },
+    /// Sent before the tick `tick` runs.
+    State {
+        tick: u64,
+        paddles: Vec<Rect>,
+        ball: Rect,
+        ball_velocity: [f64; 2],
+        blocks: Vec<BlockState>,
+        score: u32,
+        lives: u32,
+        level: usize,
+    },
+    End {
+        /// game_over, tick_limit or quit.
+        outcome: String,
+        ticks: u64,
+        score: u32,
+    },
+}
+
+impl Message {
+    fn state(controller: &GameboardController) -> Self {
+        let board = &controller.gameboard;
+        let ball = &board.ball.body;
+        Message::State {
+            tick: controller.tick,
+            paddles: board.players.iter().map(|p| Rect::of(&p.body.hitbox)).collect(),
+            ball: Rect::of(&ball.hitbox),
+            ball_velocity: [ball.velocity.x, ball.velocity.y],
+            blocks: board
+                .blocks
+                .iter()
+                .map(|block| BlockState {
+                    rect: Rect::of(&block.body.hitbox),
+                    hits: match block.kind {
+                        BlockKind::Normal => Some(1),
+                        BlockKind::MultiHit(hits) => Some(hits),
+                        BlockKind::Indestructible => None,
+                    },
+                })
+                .collect(),
+            score: board.score,
+            lives: board.lives,
+            level: controller.campaign.as_ref().map_or(1, |c| c.current + 1),
+        }
+    }
+}
+
+/// Reads the bot's lines on a thread of its own, so the game can go on while the bot thinks.
+fn spawn_reader<R: BufRead + Send + 'static>(reader: R) -> Receiver<io::Result<String>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in reader.lines() {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn parse(line: io::Result<String>) -> Result<Command, BotError> {
+    let line = line?;
+    serde_json::from_str(&line).map_err(|e| BotError::Protocol(format!("{}: {}", e, line)))
+}
+
+/// A game played by a bot on the other end of `writer` and the reader given to `new`.
+pub struct BotSession<W: Write> {
+    pub controller: GameboardController,
+    writer: W,
+    commands: Receiver<io::Result<String>>,
+    lockstep: bool,
+}
+
+impl<W: Write> BotSession<W> {
+    pub fn new<R: BufRead + Send + 'static>(controller: GameboardController, reader: R, writer: W, lockstep: bool) -> Self {
+        Self {
+            controller,
+            writer,
+            commands: spawn_reader(reader),
+            lockstep,
+        }
+    }
+
+    fn send(&mut self, message: &Message) -> Result<(), BotError> {
+        serde_json::to_writer(&mut self.writer, message).map_err(io::Error::from)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn handshake(&mut self) -> Result<(), BotError> {
+        let physics = &self.controller.gameboard.physics;
+        let hello = Message::Hello {
+            version: PROTOCOL_VERSION,
+            board_size: self.controller.gameboard.size,
+            paddle_size: [physics.paddle_size.x, physics.paddle_size.y],
+            ball_size: physics.ball_size,
+            dt: DT,
+            lockstep: self.lockstep,
+            paddles: self.controller.gameboard.players.len(),
+        };
+        self.send(&hello)?;
+
+        match self.commands.recv_timeout(HANDSHAKE_TIMEOUT) {
+            Ok(line) => match parse(line)? {
+                Command::Ready { .. } => Ok(()),
+                other => Err(BotError::Protocol(format!("expected ready, got {:?}", other))),
+            },
+            Err(RecvTimeoutError::Timeout) => Err(BotError::Protocol("no answer to hello".to_string())),
+            Err(RecvTimeoutError::Disconnected) => Err(BotError::Disconnected),
+        }
+    }
+
+    /// Applies a command, returns false if the bot wants to stop.
+    fn apply(&mut self, command: Command) -> Result<bool, BotError> {
+        match command {
+            Command::Ready { .. } => return Err(BotError::Protocol("ready sent twice".to_string())),
+            Command::Move { direction } => self.controller.hold_direction(match direction {
+                Move::Left => Direction::Left,
+                Move::Right => Direction::Right,
+                Move::Idle => Direction::Idle,
+            }),
+            Command::Target { x } if x.is_finite() => self.controller.handle_input(Input::PaddleTarget(x)),
+            Command::Target { x } => return Err(BotError::Protocol(format!("target {} is not a number", x))),
+            Command::Wait => (),
+            Command::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Applies the commands for the coming tick, returns false if the bot wants to stop.
+    fn receive(&mut self) -> Result<bool, BotError> {
+        if self.lockstep {
+            let line = self.commands.recv().map_err(|_| BotError::Disconnected)?;
+            return self.apply(parse(line)?);
+        }
+
+        loop {
+            match self.commands.try_recv() {
+                Ok(line) => {
+                    let command = parse(line)?;
+                    if !self.apply(command)? {
+                        return Ok(false);
+                    }
+                }
+                Err(TryRecvError::Empty) => return Ok(true),
+                // Nobody is left to steer
+                Err(TryRecvError::Disconnected) => return Ok(false),
+            }
+        }
+    }
+
+    /// Plays until the game is finished, `max_ticks` ticks ran or the bot quits.
+    pub fn run(&mut self, max_ticks: Option<u64>) -> Result<Summary, BotError> {
+        self.handshake()?;
+
+        let start = Instant::now();
+        let first_tick = self.controller.tick;
+        let outcome = loop {
+            if self.controller.is_game_finished() {
+                break Outcome::GameOver;
+            }
+            if max_ticks.is_some_and(|max| self.controller.tick >= max) {
+                break Outcome::TickLimit;
+            }
+
+            self.send(&Message::state(&self.controller))?;
+            if !self.receive()? {
+                break Outcome::Quit;
+            }
+            self.controller.advance(DT);
+
+            if !self.lockstep {
+                let due = start + Duration::from_secs_f64((self.controller.tick - first_tick) as f64 * DT);
+                thread::sleep(due.saturating_duration_since(Instant::now()));
+            }
+        };
+
+        let summary = Summary::new(&self.controller, outcome);
+        let end = Message::End {
+            outcome: match outcome {
+                Outcome::GameOver => "game_over",
+                Outcome::TickLimit => "tick_limit",
+                Outcome::ReplayEnd => "replay_end",
+                Outcome::Quit => "quit",
+            }
+            .to_string(),
+            ticks: summary.ticks,
+            score: summary.score,
+        };
+        // The bot may already be gone after quitting
+        if self.send(&end).is_err() && outcome != Outcome::Quit {
+            return Err(BotError::Disconnected);
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboard::Gameboard;
+    use serde_json::Value;
+    use std::io::Cursor;
+
+    fn session(input: &str, lockstep: bool) -> BotSession<Vec<u8>> {
+        let controller = GameboardController::new(Gameboard::new(400.));
+        BotSession::new(controller, Cursor::new(input.to_string().into_bytes()), Vec::new(), lockstep)
+    }
+
+    fn messages(session: &BotSession<Vec<u8>>) -> Vec<Value> {
+        String::from_utf8(session.writer.clone())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_lockstep_session() {
+        let mut input = "{\"type\":\"ready\",\"name\":\"test\"}\n".to_string();
+        input += "{\"type\":\"move\",\"direction\":\"left\"}\n";
+        input += &"{\"type\":\"wait\"}\n".repeat(9);
+        input += "{\"type\":\"quit\"}\n";
+        let mut session = session(&input, true);
+
+        let summary = session.run(None).unwrap();
+        assert_eq!(summary.outcome, Outcome::Quit);
+        assert_eq!(summary.ticks, 10);
+
+        let messages = messages(&session);
+        assert_eq!(messages[0]["type"], "hello");
+        assert_eq!(messages[0]["board_size"], 400.);
+        assert_eq!(messages[0]["lockstep"], true);
+        assert_eq!(messages.len(), 1 + 11 + 1);
+        assert_eq!(messages[1]["type"], "state");
+        assert_eq!(messages[11]["tick"], 10);
+        assert!(messages[11]["paddles"][0]["x"].as_f64().unwrap() < messages[1]["paddles"][0]["x"].as_f64().unwrap());
+        assert_eq!(messages[12]["type"], "end");
+        assert_eq!(messages[12]["outcome"], "quit");
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let mut bad_handshake = session("{\"type\":\"wait\"}\n", true);
+        assert!(matches!(bad_handshake.run(None), Err(BotError::Protocol(_))));
+
+        let mut bad_command = session("{\"type\":\"ready\"}\n{\"type\":\"jump\"}\n", true);
+        assert!(matches!(bad_command.run(None), Err(BotError::Protocol(_))));
+
+        let mut gone = session("{\"type\":\"ready\"}\n", true);
+        assert!(matches!(gone.run(None), Err(BotError::Disconnected)));
+    }
+}
@@ -0,0 +1,197 @@
+//! Settings loaded from a TOML file at startup.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::actions::Bindings;
+use crate::ai::AiSettings;
+use crate::gameboard::PhysicsSettings;
+use crate::gameboard_controller::MouseControl;
+use crate::gameboard_view::GameboardViewSettings;
+use crate::math::Vec2;
+
+/// Where the config is read from, relative to the working directory.
+pub const CONFIG_PATH: &str = "rustout.toml";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    /// A value parsed fine but makes no sense, e.g. a negative size.
+    Invalid { field: String, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "config {} is invalid: {}", path.display(), e),
+            ConfigError::Invalid { field, reason } => write!(f, "config entry {} {}", field, reason),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlSettings {
+    /// The paddle follows the mouse cursor, the keys keep working.
+    pub mouse: bool,
+}
+
+/// All settings, every section and entry is optional and falls back to the built in default.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub view: GameboardViewSettings,
+    pub physics: PhysicsSettings,
+    pub bindings: Bindings,
+    pub controls: ControlSettings,
+    pub ai: AiSettings,
+}
+
+fn invalid(field: &str, reason: &str) -> ConfigError {
+    ConfigError::Invalid {
+        field: field.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+fn check_positive(field: &str, value: f64) -> Result<(), ConfigError> {
+    if value > 0. && value.is_finite() {
+        Ok(())
+    } else {
+        Err(invalid(field, "must be a positive number"))
+    }
+}
+
+fn check_finite(field: &str, value: &Vec2) -> Result<(), ConfigError> {
+    if value.x.is_finite() && value.y.is_finite() {
+        Ok(())
+    } else {
+        Err(invalid(field, "must be finite"))
+    }
+}
+
+fn check_color(field: &str, color: &[f32; 4]) -> Result<(), ConfigError> {
+    if color.iter().all(|c| (0.0..=1.0).contains(c)) {
+        Ok(())
+    } else {
+        Err(invalid(field, "must have components between 0 and 1"))
+    }
+}
+
+impl Config {
+    /// Parses and validates a config file's contents.
+    pub fn from_str(text: &str, path: &Path) -> Result<Self, ConfigError> {
+        let config: Config = toml::from_str(text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads the config, a missing file gives the defaults.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::from_str(&text, path),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(ConfigError::Io(path.to_path_buf(), e)),
+        }
+    }
+
+    /// Mouse control for the configured view, if enabled.
+    pub fn mouse_control(&self) -> Option<MouseControl> {
+        self.controls.mouse.then(|| MouseControl::for_view(&self.view))
+    }
+
+    /// The defaults written out as a config file.
+    pub fn default_toml() -> String {
+        toml::to_string(&Self::default()).expect("The default config is always serializable")
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let view = &self.view;
+        if view.window_size.contains(&0) {
+            return Err(invalid("view.window_size", "must not be zero"));
+        }
+        check_positive("view.size", view.size)?;
+        check_color("view.background_color", &view.background_color)?;
+        check_color("view.border_color", &view.border_color)?;
+
+        let physics = &self.physics;
+        check_finite("physics.paddle_position", &physics.paddle_position)?;
+        check_positive("physics.paddle_size.x", physics.paddle_size.x)?;
+        check_positive("physics.paddle_size.y", physics.paddle_size.y)?;
+        check_positive("physics.paddle_speed", physics.paddle_speed)?;
+        // Saves store the physics as JSON, which has no infinity
+        check_positive("physics.mouse_max_speed", physics.mouse_max_speed)?;
+        check_finite("physics.ball_position", &physics.ball_position)?;
+        check_positive("physics.ball_size", physics.ball_size)?;
+        check_finite("physics.ball_velocity", &physics.ball_velocity)?;
+        if physics.ball_velocity == Vec2::zero() {
+            return Err(invalid("physics.ball_velocity", "must not be zero"));
+        }
+        check_positive("physics.wall_thickness", physics.wall_thickness)?;
+
+        if !(self.ai.error >= 0. && self.ai.error.is_finite()) {
+            return Err(invalid("ai.error", "must be a positive number or zero"));
+        }
+
+        if let Some((button, first, second)) = self.bindings.conflict() {
+            return Err(invalid(
+                &format!("bindings.{}", second.name()),
+                &format!("uses {:?} which is already bound to bindings.{}", button, first.name()),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::Action;
+    use piston::input::{Button, Key, MouseButton};
+
+    fn parse(text: &str) -> Result<Config, ConfigError> {
+        Config::from_str(text, Path::new("test.toml"))
+    }
+
+    #[test]
+    fn test_default_toml_round_trips() {
+        assert_eq!(parse(&Config::default_toml()).unwrap(), Config::default());
+        assert_eq!(parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_partial_config_keeps_defaults() {
+        let text = "[physics]\npaddle_speed = 400.0\n[bindings]\nmove_left = [{ Keyboard = \"J\" }, { Mouse = \"Left\" }]\n";
+        let config = parse(text).unwrap();
+
+        assert_eq!(config.physics.paddle_speed, 400.);
+        assert_eq!(config.physics.ball_size, PhysicsSettings::default().ball_size);
+        assert_eq!(config.bindings.action(Button::Keyboard(Key::J)), Some(Action::MoveLeft));
+        assert_eq!(config.bindings.action(Button::Mouse(MouseButton::Left)), Some(Action::MoveLeft));
+        assert_eq!(config.bindings.action(Button::Keyboard(Key::Left)), None);
+        assert_eq!(config.bindings.action(Button::Keyboard(Key::Backspace)), Some(Action::Rewind));
+        assert_eq!(config.view, GameboardViewSettings::new());
+    }
+
+    #[test]
+    fn test_invalid_entries_are_rejected() {
+        assert!(matches!(parse("[view]\nsise = 3.0\n"), Err(ConfigError::Parse(..))));
+        assert!(matches!(parse("[bindings]\nmove_left = [{ Keyboard = \"NoSuchKey\" }]\n"), Err(ConfigError::Parse(..))));
+        assert!(matches!(parse("[bindings]\njump = []\n"), Err(ConfigError::Parse(..))));
+
+        let e = parse("[physics]\nball_size = -1.0\n").unwrap_err();
+        assert_eq!(e.to_string(), "config entry physics.ball_size must be a positive number");
+        let e = parse("[physics]\nmouse_max_speed = inf\n").unwrap_err();
+        assert_eq!(e.to_string(), "config entry physics.mouse_max_speed must be a positive number");
+
+        let e = parse("[bindings]\nrewind = [{ Keyboard = \"Left\" }]\n").unwrap_err();
+        assert_eq!(e.to_string(), "config entry bindings.rewind uses Keyboard(Left) which is already bound to bindings.move_left");
+    }
+}
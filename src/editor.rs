@@ -0,0 +1,314 @@
+//! Level editor.
+
+use std::path::PathBuf;
+
+use piston::input::GenericEvent;
+
+use crate::gameboard::{BlockKind, Color, Mode, PhysicsSettings};
+use crate::gameboard_controller::GameboardController;
+use crate::generator::PALETTE;
+use crate::level::{BlockSpec, Level};
+use crate::level_code;
+use crate::math::Vec2;
+
+const KINDS: [BlockKind; 3] = [BlockKind::Normal, BlockKind::MultiHit(2), BlockKind::Indestructible];
+
+/// The grid blocks are snapped to, matches the spacing of `BlockLayout::from_rows`.
+pub struct BlockGrid {
+    pub origin: Vec2,
+    pub dimension: Vec2,
+    pub spacing: f64,
+}
+
+impl BlockGrid {
+    pub fn new() -> Self {
+        Self {
+            origin: Vec2::new(20., 20.),
+            dimension: Vec2::new(40., 10.),
+            spacing: 2.,
+        }
+    }
+
+    /// Top left corner of the grid cell containing `point`.
+    pub fn snap(&self, point: Vec2) -> Vec2 {
+        let step = self.dimension + Vec2::new(self.spacing, self.spacing);
+        let relative = point - self.origin;
+        self.origin
+            + Vec2::new(
+                (relative.x / step.x).floor() * step.x,
+                (relative.y / step.y).floor() * step.y,
+            )
+    }
+}
+
+fn contains(spec: &BlockSpec, point: Vec2) -> bool {
+    point.x >= spec.x && point.x < spec.x + spec.w && point.y >= spec.y && point.y < spec.y + spec.h
+}
+
+/// Editable level with undo and redo.
+pub struct Editor {
+    pub level: Level,
+    pub grid: BlockGrid,
+    pub size: f64,
+    pub color_index: usize,
+    pub kind_index: usize,
+    history: Vec<Vec<BlockSpec>>,
+    future: Vec<Vec<BlockSpec>>,
+}
+
+impl Editor {
+    pub fn new(level: Level, size: f64) -> Self {
+        Self {
+            level,
+            grid: BlockGrid::new(),
+            size,
+            color_index: 0,
+            kind_index: 0,
+            history: Vec::new(),
+            future: Vec::new(),
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        PALETTE[self.color_index]
+    }
+
+    pub fn kind(&self) -> BlockKind {
+        KINDS[self.kind_index]
+    }
+
+    pub fn next_color(&mut self) {
+        self.color_index = (self.color_index + 1) % PALETTE.len();
+    }
+
+    pub fn next_kind(&mut self) {
+        self.kind_index = (self.kind_index + 1) % KINDS.len();
+    }
+
+    /// The block which would be placed at `point`, if it fits on the board.
+    pub fn preview(&self, point: Vec2) -> Option<BlockSpec> {
+        let position = self.grid.snap(point);
+        let dimension = self.grid.dimension;
+        if position.x < 0. || position.y < 0. || position.x + dimension.x > self.size || position.y + dimension.y > self.size {
+            return None;
+        }
+
+        Some(BlockSpec {
+            x: position.x,
+            y: position.y,
+            w: dimension.x,
+            h: dimension.y,
+            color: self.color(),
+            kind: self.kind(),
+        })
+    }
+
+    /// The block in the grid cell of `point`, so clicks into the spacing after a block still find it.
+    /// Blocks off the grid, e.g. from imported levels, are found by the point itself.
+    fn block_at(&self, point: Vec2) -> Option<usize> {
+        let cell = self.grid.snap(point);
+        let blocks = &self.level.blocks;
+        blocks
+            .iter()
+            .position(|spec| (spec.x - cell.x).abs() < 1e-6 && (spec.y - cell.y).abs() < 1e-6)
+            .or_else(|| blocks.iter().position(|spec| contains(spec, point)))
+    }
+
+    fn commit(&mut self) {
+        self.history.push(self.level.blocks.clone());
+        self.future.clear();
+    }
+
+    /// Places a block at the snapped grid cell, or recolors the block under `point`.
+    pub fn place(&mut self, point: Vec2) {
+        let (color, kind) = (self.color(), self.kind());
+        match self.block_at(point) {
+            Some(i) => {
+                let spec = &self.level.blocks[i];
+                if spec.color == color && spec.kind == kind {
+                    return;
+                }
+                self.commit();
+                let spec = &mut self.level.blocks[i];
+                spec.color = color;
+                spec.kind = kind;
+            }
+            None => {
+                if let Some(spec) = self.preview(point) {
+                    self.commit();
+                    self.level.blocks.push(spec);
+                }
+            }
+        }
+    }
+
+    pub fn delete(&mut self, point: Vec2) {
+        if let Some(i) = self.block_at(point) {
+            self.commit();
+            self.level.blocks.remove(i);
+        }
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(blocks) = self.history.pop() {
+            self.future.push(std::mem::replace(&mut self.level.blocks, blocks));
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(blocks) = self.future.pop() {
+            self.history.push(std::mem::replace(&mut self.level.blocks, blocks));
+        }
+    }
+}
+
+/// Handles events for the level editor.
+pub struct EditorController {
+    pub editor: Editor,
+    /// Where the level is saved to.
+    pub path: PathBuf,
+    /// Top left corner of the board in window coordinates.
+    pub board_position: [f64; 2],
+    /// Mouse position in board coordinates.
+    pub cursor: Vec2,
+    /// Running game while play-testing.
+    pub playtest: Option<GameboardController>,
+    /// Physics the level is play-tested with.
+    pub physics: PhysicsSettings,
+}
+
+impl EditorController {
+    pub fn new(editor: Editor, path: PathBuf, board_position: [f64; 2], physics: PhysicsSettings) -> Self {
+        Self {
+            editor,
+            path,
+            board_position,
+            cursor: Vec2::zero(),
+            playtest: None,
+            physics,
+        }
+    }
+
+    fn toggle_playtest(&mut self) {
+        self.playtest = match self.playtest {
+            Some(_) => None,
+            None => Some(GameboardController::new(self.editor.level.make_board(
+                self.editor.size,
+                self.physics.clone(),
+                Mode::Single,
+            ))),
+        };
+    }
+
+    fn save(&self) {
+        match self.editor.level.save(&self.path) {
+            Ok(()) => println!("Saved level to {}", self.path.display()),
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    fn print_code(&self) {
+        match level_code::encode(&self.editor.level) {
+            Ok(code) => println!("Level code: {}", code),
+            Err(e) => println!("Could not create level code: {}", e),
+        }
+    }
+
+    /// Handles events.
+    pub fn event<E: GenericEvent>(&mut self, e: &E) {
+        use piston::input::{Button, ButtonState, Key, MouseButton};
+
+        if let Some(button_args) = e.button_args() {
+            if button_args.state == ButtonState::Press {
+                match button_args.button {
+                    // Not bound to any game action, the play-test gets every other key
+                    Button::Keyboard(Key::T) => return self.toggle_playtest(),
+                    _ if self.playtest.is_some() => (),
+                    Button::Keyboard(Key::C) => self.editor.next_color(),
+                    Button::Keyboard(Key::K) => self.editor.next_kind(),
+                    Button::Keyboard(Key::Z) => self.editor.undo(),
+                    Button::Keyboard(Key::Y) => self.editor.redo(),
+                    Button::Keyboard(Key::S) => self.save(),
+                    Button::Keyboard(Key::E) => self.print_code(),
+                    Button::Mouse(MouseButton::Left) => self.editor.place(self.cursor),
+                    Button::Mouse(MouseButton::Right) => self.editor.delete(self.cursor),
+                    _ => (),
+                }
+            }
+        }
+
+        if let Some([x, y]) = e.mouse_cursor_args() {
+            self.cursor = Vec2::new(x - self.board_position[0], y - self.board_position[1]);
+        }
+
+        if let Some(ref mut controller) = self.playtest {
+            controller.event(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_editor() -> Editor {
+        Editor::new(Level { name: String::new(), blocks: Vec::new() }, 400.)
+    }
+
+    #[test]
+    fn test_place_snaps_to_grid() {
+        let mut editor = empty_editor();
+
+        editor.place(Vec2::new(70., 35.));
+
+        let spec = &editor.level.blocks[0];
+        assert_eq!((spec.x, spec.y), (62., 32.));
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut editor = empty_editor();
+
+        editor.place(Vec2::new(25., 25.));
+        editor.place(Vec2::new(70., 25.));
+        editor.delete(Vec2::new(25., 25.));
+        assert_eq!(editor.level.blocks.len(), 1);
+
+        editor.undo();
+        editor.undo();
+        assert_eq!(editor.level.blocks.len(), 1);
+
+        editor.redo();
+        assert_eq!(editor.level.blocks.len(), 2);
+
+        editor.place(Vec2::new(120., 25.));
+        editor.redo();
+        assert_eq!(editor.level.blocks.len(), 3);
+    }
+
+    #[test]
+    fn test_recolor_existing_block() {
+        let mut editor = empty_editor();
+
+        editor.place(Vec2::new(25., 25.));
+        editor.next_color();
+        editor.place(Vec2::new(30., 27.));
+
+        assert_eq!(editor.level.blocks.len(), 1);
+        assert_eq!(editor.level.blocks[0].color, PALETTE[1]);
+    }
+
+    #[test]
+    fn test_click_into_spacing_finds_block() {
+        let mut editor = empty_editor();
+
+        editor.place(Vec2::new(25., 25.));
+        // Right of and below the block at (20, 20), inside its cell's spacing
+        editor.place(Vec2::new(61., 25.));
+        editor.place(Vec2::new(25., 31.));
+        assert_eq!(editor.level.blocks.len(), 1);
+
+        editor.delete(Vec2::new(61., 31.));
+        assert!(editor.level.blocks.is_empty());
+    }
+}
@@ -9,11 +9,13 @@ Switch from rect-rect intersection to vector rect intersection for collision det
 */
 
 use crate::math;
-use math::{ Vec2, Segment };
+use math::{ StateHasher, Vec2, Segment };
+
+use serde::{Deserialize, Serialize};
 
 use std::f64;
 
-#[derive(PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
 pub enum Direction {
     Idle,
     Left,
@@ -26,7 +28,7 @@ pub enum CollisionType {
     Block,
 }
 
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Rectangle {
     pub position: Vec2,
     pub dimension: Vec2,
@@ -77,6 +79,7 @@ impl Rectangle {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Body {
     pub prev_position: Vec2,
     pub hitbox: Rectangle,
@@ -97,6 +100,13 @@ impl Body {
         self.hitbox.position += self.velocity * delta;
     }
 
+    fn hash(&self, hasher: &mut StateHasher) {
+        hasher.write_vec2(&self.prev_position);
+        hasher.write_vec2(&self.hitbox.position);
+        hasher.write_vec2(&self.hitbox.dimension);
+        hasher.write_vec2(&self.velocity);
+    }
+
     pub fn tick_segment(&self) -> Segment {
         Segment(self.prev_position, self.hitbox.position)
     }
@@ -113,13 +123,94 @@ trait GameObject {
     fn despawn(&mut self);
 }
 
+/// Which way a paddle sends the ball back.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum Facing {
+    #[default]
+    Up,
+    Down,
+}
+
+impl Facing {
+    /// Vertical direction as a sign.
+    fn sign(self) -> f64 {
+        match self {
+            Facing::Up => -1.,
+            Facing::Down => 1.,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Player {
     pub body: Body,
     pub direction: Direction,
+    #[serde(default)]
+    pub facing: Facing,
+    /// Points of this player in versus mode.
+    #[serde(default)]
+    pub score: u32,
+    /// Whether the move left and move right inputs are currently held.
+    #[serde(default)]
+    pub left_held: bool,
+    #[serde(default)]
+    pub right_held: bool,
+    /// Board x coordinate the paddle's center moves towards when following the mouse.
+    #[serde(default)]
+    pub target: Option<f64>,
+}
+
+impl Player {
+    /// Presses or releases the input moving in `direction`.
+    /// The most recently pressed input wins, releasing it hands over to the other one if still held.
+    pub fn steer(&mut self, direction: Direction, pressed: bool) {
+        // The keyboard takes over from the mouse
+        self.target = None;
+
+        match direction {
+            Direction::Left => self.left_held = pressed,
+            Direction::Right => self.right_held = pressed,
+            Direction::Idle => return,
+        }
+
+        if pressed {
+            self.direction = direction;
+        } else if self.direction == direction {
+            self.direction = if self.left_held {
+                Direction::Left
+            } else if self.right_held {
+                Direction::Right
+            } else {
+                Direction::Idle
+            };
+        }
+    }
+
+    /// Moves the paddle's center towards `target`, by at most `max_speed * delta`.
+    fn move_towards(&mut self, target: f64, max_speed: f64, delta: f64) {
+        let center = self.body.hitbox.position.x + self.body.hitbox.dimension.x / 2.;
+        let step = max_speed * delta;
+        let dx = (target - center).clamp(-step, step);
+
+        // Wall collisions push the paddle back depending on the direction
+        self.direction = if dx < 0. {
+            Direction::Left
+        } else if dx > 0. {
+            Direction::Right
+        } else {
+            Direction::Idle
+        };
+        self.body.prev_position = self.body.hitbox.position;
+        self.body.hitbox.position.x += dx;
+    }
 }
 
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Ball {
     pub body: Body,
+    /// Index of the player who touched the ball last.
+    #[serde(default)]
+    pub last_hit: Option<usize>,
 }
 
 fn get_shortest_distance_segment_with_intersection(
@@ -221,7 +312,7 @@ impl GameObject for Player {
                     - 1.0;
 
                 other_body.velocity =
-                    Vec2::new(scaled, -1.0).set_length(other_body.velocity.length());
+                    Vec2::new(scaled, self.facing.sign()).set_length(other_body.velocity.length());
             }
             _ => (),
         }
@@ -272,6 +363,7 @@ impl GameObject for Ball {
     fn despawn(&mut self) {}
 }
 
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Wall {
     pub body: Body,
 }
@@ -296,22 +388,47 @@ impl GameObject for Wall {
     fn despawn(&mut self) {}
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum BlockKind {
+    /// Destroyed on the first hit.
+    #[default]
+    Normal,
+    /// Destroyed once the remaining hits reach zero.
+    MultiHit(u32),
+    /// Never destroyed, not required to clear a level.
+    Indestructible,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Block {
     pub body: Body,
-    pub color: Color
+    pub color: Color,
+    pub kind: BlockKind,
+    pub despawned: bool,
 }
 
 impl Block {
-    fn new(position: Vec2, dimension: Vec2, color: Color) -> Self {
+    pub fn new(position: Vec2, dimension: Vec2, color: Color) -> Self {
         Self {
             body: Body::new(
                 Rectangle::new(position.x, position.y, dimension.x, dimension.y),
                 Vec2::zero(),
             ),
             color,
+            kind: BlockKind::Normal,
+            despawned: false,
         }
     }
 
+    pub fn with_kind(mut self, kind: BlockKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn is_destructible(&self) -> bool {
+        self.kind != BlockKind::Indestructible
+    }
+
     fn make_factory(dimension: Vec2, color: Color) -> impl Fn(Vec2) -> Self {
         move |position: Vec2| Self::new(position, dimension, color)
     } 
@@ -336,19 +453,89 @@ impl GameObject for Block {
     fn on_collision(&mut self, _: &mut dyn GameObject) {}
 
     fn despawn(&mut self) {
-        self.body.hitbox.position = Vec2::new(-1000., -1000.)
+        match self.kind {
+            BlockKind::Indestructible => return,
+            BlockKind::MultiHit(hits) if hits > 1 => {
+                self.kind = BlockKind::MultiHit(hits - 1);
+                return;
+            }
+            _ => (),
+        }
+
+        self.body.hitbox.position = Vec2::new(-1000., -1000.);
+        self.despawned = true;
     }
 }
 
-type Color = [u8; 4];
+pub type Color = [u8; 4];
 
-enum ColorSettings {
+pub enum ColorSettings {
     Single(Color),
     PerRow(Vec<Color>),
     PerBlock(Vec<Vec<Color>>),
 }
 
-struct BlockLayout {
+impl ColorSettings {
+    // Rows and columns without an entry fall back to the last one given
+    fn color_at(&self, row: usize, column: usize) -> Color {
+        fn pick<T: Copy>(v: &[T], i: usize) -> Option<T> {
+            v.get(i).or_else(|| v.last()).copied()
+        }
+
+        let black = [0, 0, 0, 255];
+        match self {
+            ColorSettings::Single(c) => *c,
+            ColorSettings::PerRow(c_vec) => pick(c_vec, row).unwrap_or(black),
+            ColorSettings::PerBlock(c_mat) => c_mat
+                .get(row)
+                .or_else(|| c_mat.last())
+                .and_then(|c_vec| pick(c_vec, column))
+                .unwrap_or(black),
+        }
+    }
+}
+
+/// Horizontal placement of a layout within the board.
+#[derive(Clone, Copy)]
+pub enum Alignment {
+    /// Offset from the left wall.
+    Left(f64),
+    Center,
+    /// Offset from the right wall.
+    Right(f64),
+}
+
+/// Shared settings of the shaped layout builders.
+pub struct LayoutSettings {
+    pub dimension: Vec2,
+    /// Gap between neighbouring blocks.
+    pub spacing: Vec2,
+    pub alignment: Alignment,
+    /// Y position of the first row.
+    pub top: f64,
+    pub board_size: f64,
+    pub colors: ColorSettings,
+}
+
+impl LayoutSettings {
+    /// Default block size and spacing, centered on the board.
+    pub fn new(board_size: f64, colors: ColorSettings) -> Self {
+        Self {
+            dimension: Vec2::new(40., 10.),
+            spacing: Vec2::new(2., 2.),
+            alignment: Alignment::Center,
+            top: 20.,
+            board_size,
+            colors,
+        }
+    }
+
+    fn cell(&self) -> Vec2 {
+        self.dimension + self.spacing
+    }
+}
+
+pub struct BlockLayout {
     pub blocks: Vec<Block>,
 }
 
@@ -396,59 +583,444 @@ impl BlockLayout {
             }
         }
     }
+
+    // Places rows of cells, each row shifted right by its offset given in cells
+    fn place_rows(rows: &[(f64, Vec<bool>)], settings: &LayoutSettings) -> Vec<Block> {
+        let cell = settings.cell();
+        let width_in_cells = rows
+            .iter()
+            .map(|(offset, row)| offset + row.len() as f64)
+            .fold(0., f64::max);
+        let width = width_in_cells * cell.x - settings.spacing.x;
+
+        let left = match settings.alignment {
+            Alignment::Left(margin) => margin,
+            Alignment::Center => (settings.board_size - width) / 2.,
+            Alignment::Right(margin) => settings.board_size - margin - width,
+        };
+
+        let mut blocks = Vec::new();
+        for (i, (offset, row)) in rows.iter().enumerate() {
+            for (j, &filled) in row.iter().enumerate() {
+                if filled {
+                    let position = Vec2::new(left + (offset + j as f64) * cell.x, settings.top + i as f64 * cell.y);
+                    blocks.push(Block::new(position, settings.dimension, settings.colors.color_at(i, j)));
+                }
+            }
+        }
+
+        blocks
+    }
+
+    /// Places a block for every set cell of `mask`, indexed by row then column.
+    pub fn from_mask(mask: &[Vec<bool>], settings: &LayoutSettings) -> Vec<Block> {
+        let rows: Vec<(f64, Vec<bool>)> = mask.iter().map(|row| (0., row.clone())).collect();
+        Self::place_rows(&rows, settings)
+    }
+
+    /// Completes each row of `left_half` with its mirror image.
+    pub fn mirrored(left_half: &[Vec<bool>], settings: &LayoutSettings) -> Vec<Block> {
+        let mask: Vec<Vec<bool>> = left_half
+            .iter()
+            .map(|row| row.iter().chain(row.iter().rev()).copied().collect())
+            .collect();
+        Self::from_mask(&mask, settings)
+    }
+
+    /// Rows like a brick wall, every other row is shifted by half a block.
+    pub fn brick_wall(blocks_per_row: u32, rows: u32, settings: &LayoutSettings) -> Vec<Block> {
+        let rows: Vec<(f64, Vec<bool>)> = (0..rows)
+            .map(|i| (if i % 2 == 1 { 0.5 } else { 0. }, vec![true; blocks_per_row as usize]))
+            .collect();
+        Self::place_rows(&rows, settings)
+    }
+
+    /// A pyramid with one block at the top and `rows` blocks at the bottom.
+    pub fn pyramid(rows: u32, settings: &LayoutSettings) -> Vec<Block> {
+        let rows: Vec<(f64, Vec<bool>)> = (0..rows)
+            .map(|i| ((rows - 1 - i) as f64 / 2., vec![true; i as usize + 1]))
+            .collect();
+        Self::place_rows(&rows, settings)
+    }
+
+    /// A diamond which is `width` blocks wide in its middle row.
+    pub fn diamond(width: u32, settings: &LayoutSettings) -> Vec<Block> {
+        if width == 0 {
+            return Vec::new();
+        }
+        let rows: Vec<(f64, Vec<bool>)> = (0..2 * width - 1)
+            .map(|i| {
+                let count = if i < width { i + 1 } else { 2 * width - 1 - i };
+                ((width - count) as f64 / 2., vec![true; count as usize])
+            })
+            .collect();
+        Self::place_rows(&rows, settings)
+    }
+
+    /// An ellipse filling a grid of `blocks_per_row` x `rows` cells.
+    pub fn ellipse(blocks_per_row: u32, rows: u32, settings: &LayoutSettings) -> Vec<Block> {
+        let (rx, ry) = (blocks_per_row as f64 / 2., rows as f64 / 2.);
+        let mask: Vec<Vec<bool>> = (0..rows)
+            .map(|i| {
+                (0..blocks_per_row)
+                    .map(|j| {
+                        let dx = (j as f64 + 0.5 - rx) / rx;
+                        let dy = (i as f64 + 0.5 - ry) / ry;
+                        dx * dx + dy * dy <= 1.
+                    })
+                    .collect()
+            })
+            .collect();
+        Self::from_mask(&mask, settings)
+    }
+
+    /// A circle with the given radius in board units, blocks are not square so the grid is scaled accordingly.
+    pub fn circle(radius: f64, settings: &LayoutSettings) -> Vec<Block> {
+        let cell = settings.cell();
+        Self::ellipse((2. * radius / cell.x).round() as u32, (2. * radius / cell.y).round() as u32, settings)
+    }
+}
+
+/// Lives a fresh game starts with.
+pub const STARTING_LIVES: u32 = 3;
+
+/// Returns the blocks of the default level.
+pub fn default_blocks() -> Vec<Block> {
+    BlockLayout::from_rows(Vec2::new(20., 20.), Vec2::new(40., 10.), ColorSettings::Single([255, 255, 0, 255]), 8, 10)
+}
+
+/// Sizes and speeds of the paddle, ball and walls.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhysicsSettings {
+    /// Top left corner of the paddle at the start.
+    pub paddle_position: Vec2,
+    pub paddle_size: Vec2,
+    /// Horizontal paddle speed in units per second.
+    pub paddle_speed: f64,
+    /// Top left corner of a newly served ball.
+    pub ball_position: Vec2,
+    /// Fastest the paddle follows the mouse in units per second, a huge value like `1e9` lets it jump to the cursor.
+    pub mouse_max_speed: f64,
+    /// Edge length of the square ball.
+    pub ball_size: f64,
+    /// Velocity of a newly served ball.
+    pub ball_velocity: Vec2,
+    pub wall_thickness: f64,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        Self {
+            paddle_position: Vec2::new(150., 350.),
+            paddle_size: Vec2::new(100., 15.),
+            paddle_speed: 250.,
+            mouse_max_speed: 1000.,
+            ball_position: Vec2::new(165., 250.),
+            ball_size: 10.,
+            ball_velocity: Vec2::new(0., 300.),
+            wall_thickness: 10.,
+        }
+    }
+}
+
+fn spawn_ball(physics: &PhysicsSettings) -> Ball {
+    Ball {
+        body: Body::new(
+            Rectangle::new(physics.ball_position.x, physics.ball_position.y, physics.ball_size, physics.ball_size),
+            physics.ball_velocity,
+        ),
+        last_hit: None,
+    }
+}
+
+/// Serves the ball in versus mode from the horizontal center, a quarter of the board away from
+/// the side it is flying towards.
+fn serve_ball(size: f64, physics: &PhysicsSettings, towards: Facing) -> Ball {
+    let s = physics.ball_size;
+    let (y, sign) = match towards {
+        Facing::Down => (size * 0.75, 1.),
+        Facing::Up => (size * 0.25, -1.),
+    };
+    let speed = physics.ball_velocity.length();
+    Ball {
+        body: Body::new(Rectangle::new((size - s) / 2., y - s / 2., s, s), Vec2::new(0., sign * speed)),
+        last_hit: None,
+    }
+}
+
+/// How two co-op paddles are placed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum CoopLayout {
+    /// Both on the paddle line, each starting in its half.
+    SideBySide,
+    /// The second paddle a few paddle heights above the first.
+    Stacked,
+}
+
+/// Who plays on the board.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum Mode {
+    #[default]
+    Single,
+    /// Two paddles sharing lives and score. Always exactly two, keys are bound per player
+    /// but mouse, AI and bot paddle targets only ever move player one.
+    Coop(CoopLayout),
+    /// Player one at the bottom against player two at the top, both sides are goals.
+    Versus,
+}
+
+/// Points for getting the ball past the opponent in versus mode, a block is worth one.
+pub const GOAL_POINTS: u32 = 5;
+/// Points ending a versus game.
+pub const WINNING_SCORE: u32 = 50;
+
+fn spawn_paddle(x: f64, y: f64, facing: Facing, physics: &PhysicsSettings) -> Player {
+    let hitbox = Rectangle {
+        position: Vec2::new(x, y),
+        dimension: physics.paddle_size,
+    };
+    Player {
+        body: Body::new(hitbox, Vec2::new(physics.paddle_speed, 0.0)),
+        direction: Direction::Idle,
+        facing,
+        score: 0,
+        left_held: false,
+        right_held: false,
+        target: None,
+    }
+}
+
+fn spawn_paddles(size: f64, physics: &PhysicsSettings, mode: Mode) -> Vec<Player> {
+    let Vec2 { x, y } = physics.paddle_position;
+    let Vec2 { x: w, y: h } = physics.paddle_size;
+
+    match mode {
+        Mode::Single => vec![spawn_paddle(x, y, Facing::Up, physics)],
+        Mode::Coop(CoopLayout::SideBySide) => vec![
+            spawn_paddle(size / 3. - w / 2., y, Facing::Up, physics),
+            spawn_paddle(size * 2. / 3. - w / 2., y, Facing::Up, physics),
+        ],
+        Mode::Coop(CoopLayout::Stacked) => {
+            vec![spawn_paddle(x, y, Facing::Up, physics), spawn_paddle(x, y - 3. * h, Facing::Up, physics)]
+        }
+        Mode::Versus => {
+            vec![spawn_paddle(x, y, Facing::Up, physics), spawn_paddle(x, size - y - h, Facing::Down, physics)]
+        }
+    }
+}
+
+/// Walls around the board. Versus mode only has side walls, the top and bottom are goals.
+fn spawn_walls(size: f64, physics: &PhysicsSettings, mode: Mode) -> Vec<Wall> {
+    let t = physics.wall_thickness;
+    let wall = |x, y, w, h| Wall {
+        body: Body::new(Rectangle::new(x, y, w, h), Vec2::zero()),
+    };
+
+    match mode {
+        Mode::Versus => vec![wall(-t, -t, t, size + 2. * t), wall(size, -t, t, size + 2. * t)],
+        _ => vec![
+            wall(-t, -t, t, size + t),
+            wall(0., -t, size, t),
+            wall(size, -t, t, size + t),
+        ],
+    }
+}
+
+/// Moves the blocks up or down so they are centered vertically on the board.
+fn center_vertically(blocks: &mut [Block], size: f64) {
+    let top = blocks.iter().map(|b| b.body.hitbox.position.y).fold(f64::INFINITY, f64::min);
+    let bottom = blocks.iter().map(|b| b.body.hitbox.position.y + b.body.hitbox.dimension.y).fold(f64::NEG_INFINITY, f64::max);
+    if blocks.is_empty() {
+        return;
+    }
+
+    let offset = (size - top - bottom) / 2.;
+    for block in blocks {
+        block.body.hitbox.position.y += offset;
+        block.body.prev_position = block.body.hitbox.position;
+    }
+}
+
+/// Pushes two overlapping paddles apart, each back along the way it came in proportion to how far it moved.
+fn separate_paddles(a: &mut Player, b: &mut Player) {
+    let (ha, hb) = (&a.body.hitbox, &b.body.hitbox);
+    if !ha.intersects(hb) {
+        return;
+    }
+
+    let a_is_left = ha.position.x + ha.dimension.x / 2. < hb.position.x + hb.dimension.x / 2.;
+    let (overlap, a_push) = if a_is_left {
+        (ha.position.x + ha.dimension.x - hb.position.x, -1.)
+    } else {
+        (hb.position.x + hb.dimension.x - ha.position.x, 1.)
+    };
+
+    // Distance each paddle moved towards the other this tick
+    let a_moved = ((a.body.prev_position.x - ha.position.x) * a_push).max(0.);
+    let b_moved = ((hb.position.x - b.body.prev_position.x) * a_push).max(0.);
+    let a_share = if a_moved + b_moved > 0. { a_moved / (a_moved + b_moved) } else { 0.5 };
+
+    a.body.hitbox.position.x += a_push * overlap * a_share;
+    b.body.hitbox.position.x -= a_push * overlap * (1. - a_share);
 }
 
 /// Stores game board information.
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Gameboard {
-    pub player: Player,
+    /// The first paddle is controlled by player one and the mouse.
+    pub players: Vec<Player>,
     pub ball: Ball,
-    pub walls: [Wall; 3],
+    pub walls: Vec<Wall>,
     pub blocks: Vec<Block>,
     pub size: f64,
+    /// Number of blocks destroyed so far.
+    pub score: u32,
+    /// Balls left before the game is over.
+    pub lives: u32,
+    /// Saves from before the settings existed were played with the defaults.
+    #[serde(default)]
+    pub physics: PhysicsSettings,
+    #[serde(default)]
+    pub mode: Mode,
 }
 
 impl Gameboard {
     /// Creates a new game board.
     pub fn new(size: f64) -> Self {
+        Self::with_blocks(size, default_blocks())
+    }
+
+    /// Creates a new game board with the given blocks instead of the default layout.
+    pub fn with_blocks(size: f64, blocks: Vec<Block>) -> Self {
+        Self::with_physics(size, blocks, PhysicsSettings::default())
+    }
+
+    /// Creates a new game board with the given blocks and physics constants.
+    pub fn with_physics(size: f64, blocks: Vec<Block>, physics: PhysicsSettings) -> Self {
+        Self::with_mode(size, blocks, physics, Mode::Single)
+    }
+
+    /// Creates a new game board with the given blocks, physics constants and paddles.
+    /// In versus mode the blocks are moved to the middle of the board.
+    pub fn with_mode(size: f64, mut blocks: Vec<Block>, physics: PhysicsSettings, mode: Mode) -> Self {
+        let ball = match mode {
+            Mode::Versus => {
+                center_vertically(&mut blocks, size);
+                serve_ball(size, &physics, Facing::Down)
+            }
+            _ => spawn_ball(&physics),
+        };
+
         Self {
-            player: Player {
-                body: Body::new(
-                    Rectangle::new(150.0, 350.0, 100.0, 15.0),
-                    Vec2::new(250.0, 0.0),
-                ),
-                direction: Direction::Idle,
-            },
-            ball: Ball {
-                body: Body::new(
-                    Rectangle::new(165.0, 250.0, 10.0, 10.0),
-                    Vec2::new(0.0, 300.0),
-                ),
-            },
-            walls: [
-                Wall {
-                    body: Body::new(Rectangle::new(-10., -10., 10., size + 10.), Vec2::zero()),
-                },
-                Wall {
-                    body: Body::new(Rectangle::new(0., -10., size, 10.), Vec2::zero()),
-                },
-                Wall {
-                    body: Body::new(Rectangle::new(size, -10., 10., size + 10.), Vec2::zero()),
-                },
-            ],
-            blocks: BlockLayout::from_rows(Vec2::new(20., 20.), Vec2::new(40., 10.), ColorSettings::Single([255, 255, 0, 255]), 8, 10),
+            players: spawn_paddles(size, &physics, mode),
+            ball,
+            walls: spawn_walls(size, &physics, mode),
+            blocks,
             size,
+            score: 0,
+            lives: STARTING_LIVES,
+            physics,
+            mode,
+        }
+    }
+
+    /// Applies changed physics constants to a game in progress where it is safe to do so.
+    /// The paddles keep their centers and the ball keeps flying, new ball settings apply from the next serve.
+    pub fn apply_physics(&mut self, physics: PhysicsSettings) {
+        for player in &mut self.players {
+            let paddle = &mut player.body;
+            let center = paddle.hitbox.position.x + paddle.hitbox.dimension.x / 2.;
+            paddle.hitbox.dimension = physics.paddle_size;
+            paddle.hitbox.position.x =
+                (center - physics.paddle_size.x / 2.).clamp(0., (self.size - physics.paddle_size.x).max(0.));
+            paddle.hitbox.position.y = paddle.hitbox.position.y.min(self.size - physics.paddle_size.y);
+            paddle.prev_position = paddle.hitbox.position;
+            paddle.velocity.x = physics.paddle_speed.copysign(paddle.velocity.x);
+        }
+        if let [a, b] = self.players.as_mut_slice() {
+            separate_paddles(a, b);
+        }
+
+        // Walls only grow outwards, so they never overlap anything on the board
+        self.walls = spawn_walls(self.size, &physics, self.mode);
+        self.physics = physics;
+    }
+
+    /// True once every destructible block has been destroyed.
+    pub fn is_cleared(&self) -> bool {
+        !self.blocks.iter().any(Block::is_destructible)
+    }
+
+    /// True once the last ball has been lost, or in versus mode once a player reached `WINNING_SCORE`.
+    pub fn is_game_over(&self) -> bool {
+        match self.mode {
+            Mode::Versus => self.players.iter().any(|p| p.score >= WINNING_SCORE),
+            _ => self.lives == 0,
         }
     }
 
+    /// Hash of the full simulation state, two boards hash equal only if they behave identically from here on.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+
+        hasher.write_u64(self.players.len() as u64);
+        for player in &self.players {
+            player.body.hash(&mut hasher);
+            hasher.write_u64(player.direction as u64);
+            hasher.write_u64(player.facing as u64);
+            hasher.write_u64(u64::from(player.score));
+            hasher.write_u64(u64::from(player.left_held));
+            hasher.write_u64(u64::from(player.right_held));
+            hasher.write_f64(player.target.unwrap_or(f64::NAN));
+        }
+        self.ball.body.hash(&mut hasher);
+        hasher.write_u64(self.ball.last_hit.map_or(u64::MAX, |i| i as u64));
+        hasher.write_u64(self.walls.len() as u64);
+        for wall in &self.walls {
+            wall.body.hash(&mut hasher);
+        }
+
+        hasher.write_u64(self.blocks.len() as u64);
+        for block in &self.blocks {
+            block.body.hash(&mut hasher);
+            let (kind, hits) = match block.kind {
+                BlockKind::Normal => (0, 0),
+                BlockKind::MultiHit(hits) => (1, hits),
+                BlockKind::Indestructible => (2, 0),
+            };
+            hasher.write_u64(kind);
+            hasher.write_u64(u64::from(hits));
+            hasher.write_u64(u64::from(block.despawned));
+        }
+
+        hasher.write_f64(self.size);
+        hasher.write_u64(u64::from(self.score));
+        hasher.write_u64(u64::from(self.lives));
+        hasher.finish()
+    }
+
     pub fn update(&mut self, delta: f64) {
-        self.player.update(delta);
+        if self.is_game_over() {
+            return;
+        }
+
+        for player in &mut self.players {
+            match player.target {
+                Some(target) => player.move_towards(target, self.physics.mouse_max_speed, delta),
+                None => player.update(delta),
+            }
+        }
         self.ball.update(delta);
         // for block in &mut self.blocks {
         //     block.update(delta);
         // }
 
-        if self.player.body.hitbox.intersects(&self.ball.body.hitbox) {
-            self.player.on_collision(&mut self.ball);
+        for (i, player) in self.players.iter_mut().enumerate() {
+            if player.body.hitbox.intersects(&self.ball.body.hitbox) {
+                player.on_collision(&mut self.ball);
+                self.ball.last_hit = Some(i);
+            }
         }
 
         for wall in &mut self.walls {
@@ -456,11 +1028,18 @@ impl Gameboard {
                 self.ball.on_collision(wall)
             }
 
-            if self.player.body.hitbox.intersects(&wall.body.hitbox) {
-                self.player.on_collision(wall)
+            for player in &mut self.players {
+                if player.body.hitbox.intersects(&wall.body.hitbox) {
+                    player.on_collision(wall)
+                }
             }
         }
 
+        // Walls were resolved first, a paddle is only pushed back the way it came
+        if let [a, b] = self.players.as_mut_slice() {
+            separate_paddles(a, b);
+        }
+
         for block in &mut self.blocks {
             if self.ball.body.hitbox.intersects(&block.body.hitbox) {
                 self.ball.on_collision(block);
@@ -468,5 +1047,107 @@ impl Gameboard {
                 break;
             }
         }
+
+        let block_count = self.blocks.len();
+        self.blocks.retain(|block| !block.despawned);
+        let destroyed = (block_count - self.blocks.len()) as u32;
+        self.score += destroyed;
+
+        if self.mode == Mode::Versus {
+            if let Some(player) = self.ball.last_hit.and_then(|i| self.players.get_mut(i)) {
+                player.score += destroyed;
+            }
+            return self.check_goals();
+        }
+
+        // The ball fell past the paddle
+        if self.ball.body.hitbox.position.y > self.size {
+            self.lives -= 1;
+            self.ball = spawn_ball(&self.physics);
+        }
+    }
+
+    /// Awards the opponent when the ball left the board past a paddle and serves it towards the side that conceded.
+    fn check_goals(&mut self) {
+        let hitbox = &self.ball.body.hitbox;
+        let (scorer, towards) = if hitbox.position.y > self.size {
+            (1, Facing::Down)
+        } else if hitbox.position.y + hitbox.dimension.y < 0. {
+            (0, Facing::Up)
+        } else {
+            return;
+        };
+
+        if let Some(player) = self.players.get_mut(scorer) {
+            player.score += GOAL_POINTS;
+        }
+        self.ball = serve_ball(self.size, &self.physics, towards);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> LayoutSettings {
+        LayoutSettings {
+            alignment: Alignment::Left(0.),
+            top: 0.,
+            ..LayoutSettings::new(400., ColorSettings::Single([255, 255, 0, 255]))
+        }
+    }
+
+    // Cell of each block, blocks are placed on a grid of 42 x 12 units
+    fn cells(blocks: &[Block]) -> Vec<(i64, i64)> {
+        blocks
+            .iter()
+            .map(|b| {
+                let position = b.body.hitbox.position;
+                ((position.x / 21.).round() as i64, (position.y / 12.).round() as i64)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diamond() {
+        assert!(BlockLayout::diamond(0, &settings()).is_empty());
+        assert_eq!(cells(&BlockLayout::diamond(1, &settings())), vec![(0, 0)]);
+
+        // Rows are centered in half cells
+        let diamond = BlockLayout::diamond(3, &settings());
+        assert_eq!(cells(&diamond), vec![(2, 0), (1, 1), (3, 1), (0, 2), (2, 2), (4, 2), (1, 3), (3, 3), (2, 4)]);
+    }
+
+    #[test]
+    fn test_ellipse_and_circle() {
+        assert!(BlockLayout::ellipse(0, 0, &settings()).is_empty());
+
+        let ellipse = BlockLayout::ellipse(4, 4, &settings());
+        assert_eq!(ellipse.len(), 12);
+        assert!(!cells(&ellipse).contains(&(0, 0)));
+        assert!(cells(&ellipse).contains(&(0, 1)));
+
+        // 84 units across are two cells wide and seven cells high
+        let circle = BlockLayout::circle(42., &settings());
+        let cells = cells(&circle);
+        assert_eq!(cells.iter().map(|c| c.1).max(), Some(6));
+        assert_eq!(cells.iter().map(|c| c.0).max(), Some(2));
+    }
+
+    #[test]
+    fn test_from_mask_and_mirrored() {
+        let mask = vec![vec![true, false], vec![false, true]];
+        assert_eq!(cells(&BlockLayout::from_mask(&mask, &settings())), vec![(0, 0), (2, 1)]);
+
+        let mirrored = BlockLayout::mirrored(&mask, &settings());
+        assert_eq!(cells(&mirrored), vec![(0, 0), (6, 0), (2, 1), (4, 1)]);
+
+        // Centering accounts for the spacing after the last column only once
+        let centered = LayoutSettings {
+            alignment: Alignment::Center,
+            ..settings()
+        };
+        let blocks = BlockLayout::mirrored(&mask, &centered);
+        assert_eq!(blocks[0].body.hitbox.position.x, (400. - 166.) / 2.);
     }
 }
@@ -1,57 +1,506 @@
 //! Gameboard controller.
 use piston::Event;
-use piston::input::GenericEvent;
+use piston::input::{Button, GenericEvent};
 
-use crate::gameboard::{Direction, Gameboard};
+use crate::actions::{Action, Bindings};
+use crate::ai::Ai;
+use crate::gameboard::{Direction, Gameboard, Mode, PhysicsSettings};
+use crate::gameboard_view::GameboardViewSettings;
+use crate::highscore::{self, Entry, HighScores};
+use crate::level::Campaign;
+use crate::replay::{Input, Recorder};
+use crate::rewind::{RewindBuffer, REWIND_SPEED};
+use crate::save;
+
+use std::path::Path;
+
+/// Simulation state of a controller which can be restored later.
+/// Whether the score was recorded is not part of it, going back in time never enters a game twice.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub gameboard: Gameboard,
+    pub campaign: Option<Campaign>,
+    pub tick: u64,
+}
+
+/// Where the board is drawn, so the paddle can follow the mouse cursor.
+pub struct MouseControl {
+    pub board_position: [f64; 2],
+    pub board_size: f64,
+}
+
+impl MouseControl {
+    pub fn for_view(settings: &GameboardViewSettings) -> Self {
+        Self {
+            board_position: settings.position,
+            board_size: settings.size,
+        }
+    }
+}
 
 /// Handles events for Sudoku game.
 pub struct GameboardController {
     /// Stores the gameboard state.
     pub gameboard: Gameboard,
+    /// Level progression, if the board is part of a level pack.
+    pub campaign: Option<Campaign>,
+    /// Table the final score is entered into at game over.
+    pub high_scores: Option<HighScores>,
+    /// Set once the final score of this game has been entered.
+    pub score_recorded: bool,
+    /// Zero based rank the final score reached in `high_scores`, if it made the cut.
+    pub score_rank: Option<usize>,
+    /// Number of simulation ticks run so far.
+    pub tick: u64,
+    /// Records inputs for a replay.
+    pub recorder: Option<Recorder>,
+    /// Recent history the game can be rewound through.
+    pub rewind: Option<RewindBuffer>,
+    /// Set while the rewind key is held.
+    pub rewinding: bool,
+    /// Stops the simulation, rewinding still works.
+    pub paused: bool,
+    /// Set by the step action, runs one tick on the next update while paused.
+    step_requested: bool,
+    pub bindings: Bindings,
+    /// Set when the paddle follows the mouse.
+    pub mouse: Option<MouseControl>,
+    /// Set when the computer plays the first paddle.
+    pub ai: Option<Ai>,
+    /// Index into `Action::ALL` of the action waiting for a new button.
+    rebinding: Option<usize>,
 }
 
 impl GameboardController {
     /// Creates a new gameboard controller.
     pub fn new(gameboard: Gameboard) -> Self {
-        Self { gameboard }
+        Self {
+            gameboard,
+            campaign: None,
+            high_scores: None,
+            score_recorded: false,
+            score_rank: None,
+            tick: 0,
+            recorder: None,
+            rewind: None,
+            rewinding: false,
+            paused: false,
+            step_requested: false,
+            bindings: Bindings::default(),
+            mouse: None,
+            ai: None,
+            rebinding: None,
+        }
+    }
+
+    /// Creates a new gameboard controller playing through a campaign.
+    pub fn with_campaign(campaign: Campaign, size: f64, physics: PhysicsSettings, mode: Mode) -> Self {
+        Self {
+            gameboard: campaign.make_board(size, physics, mode),
+            campaign: Some(campaign),
+            high_scores: None,
+            score_recorded: false,
+            score_rank: None,
+            tick: 0,
+            recorder: None,
+            rewind: None,
+            rewinding: false,
+            paused: false,
+            step_requested: false,
+            bindings: Bindings::default(),
+            mouse: None,
+            ai: None,
+            rebinding: None,
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            gameboard: self.gameboard.clone(),
+            campaign: self.campaign.clone(),
+            tick: self.tick,
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.gameboard = snapshot.gameboard.clone();
+        self.campaign = snapshot.campaign.clone();
+        self.tick = snapshot.tick;
+    }
+
+    /// True once the game ended, either by losing all lives or by finishing the campaign.
+    pub fn is_game_finished(&self) -> bool {
+        self.gameboard.is_game_over() || self.campaign.as_ref().is_some_and(|c| c.finished)
+    }
+
+    fn record_score(&mut self) {
+        self.score_recorded = true;
+
+        // Versus matches are between the two players, not against the high score table
+        if self.gameboard.mode == Mode::Versus {
+            let scores = self.gameboard.players.iter().map(|p| p.score.to_string()).collect::<Vec<_>>();
+            match self.gameboard.players.as_slice() {
+                [one, two] if one.score > two.score => println!("Player 1 wins {}", scores.join(" : ")),
+                [one, two] if one.score < two.score => println!("Player 2 wins {}", scores.join(" : ")),
+                _ => println!("Draw {}", scores.join(" : ")),
+            }
+            return;
+        }
+
+        let (level, mode) = match self.campaign {
+            Some(ref campaign) => (campaign.current + 1, campaign.pack.name.as_str()),
+            None => (1, "Free play"),
+        };
+        let entry = Entry::new(&highscore::player_name(), self.gameboard.score, level, mode, self.gameboard.mode);
+
+        if let Some(ref mut table) = self.high_scores {
+            self.score_rank = table.insert(entry);
+            if let Some(rank) = self.score_rank {
+                println!("New high score, rank {}!", rank + 1);
+                if let Err(e) = table.save() {
+                    println!("{}", e);
+                }
+            }
+            print!("{}", table);
+        }
+    }
+
+    fn quick_save(&self) {
+        match save::save(self, Path::new(save::QUICK_SAVE_PATH)) {
+            Ok(()) => println!("Saved game to {}", save::QUICK_SAVE_PATH),
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    fn quick_load(&mut self) {
+        // Jumping to another state would make the recording impossible to replay
+        if self.recorder.is_some() {
+            println!("Quick load is disabled while recording");
+            return;
+        }
+
+        match save::load(Path::new(save::QUICK_SAVE_PATH)) {
+            // Only the game state is taken over, settings and everything else stay as they are
+            Ok(controller) => {
+                self.restore(&controller.snapshot());
+                if self.rewind.is_some() {
+                    self.rewind = Some(RewindBuffer::new(self));
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    /// Steps back through the rewind buffer, if there is one.
+    fn rewind_step(&mut self) {
+        if let Some(mut buffer) = self.rewind.take() {
+            buffer.rewind(self, REWIND_SPEED);
+            self.rewind = Some(buffer);
+        }
+    }
+
+    fn prompt_rebinding(&self) {
+        if let Some(index) = self.rebinding {
+            let action = Action::ALL[index];
+            println!(
+                "Press a key or mouse button for {}, currently {:?}",
+                action.name(),
+                self.bindings.buttons(action)
+            );
+        }
+    }
+
+    /// Binds `button` to the action waiting for one and moves on to the next.
+    fn rebind(&mut self, button: Button) {
+        let index = match self.rebinding {
+            Some(index) => index,
+            None => return,
+        };
+
+        self.bindings.bind(Action::ALL[index], button);
+        self.rebinding = Some(index + 1).filter(|&next| next < Action::ALL.len());
+        if self.rebinding.is_none() {
+            println!("All actions rebound");
+        }
+        self.prompt_rebinding();
+    }
+
+    /// Applies an input which affects the simulation, recording it if a recorder is attached.
+    pub fn handle_input(&mut self, input: Input) {
+        let players = &mut self.gameboard.players;
+        match input {
+            Input::Action { action, pressed } => {
+                let (index, direction) = match action {
+                    Action::MoveLeft => (0, Direction::Left),
+                    Action::MoveRight => (0, Direction::Right),
+                    Action::P2MoveLeft => (1, Direction::Left),
+                    Action::P2MoveRight => (1, Direction::Right),
+                    _ => return,
+                };
+                match players.get_mut(index) {
+                    Some(player) => player.steer(direction, pressed),
+                    None => return,
+                }
+            }
+            Input::PaddleTarget(x) => players[0].target = Some(x),
+        }
+
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.record(self.tick, input);
+        }
+        if let Some(ref mut buffer) = self.rewind {
+            buffer.record_input(self.tick, input);
+        }
+    }
+
+    pub fn handle_action(&mut self, action: Action, pressed: bool) {
+        self.handle_input(Input::Action { action, pressed });
+    }
+
+    /// Presses and releases the movement actions of the first paddle so only the one for `direction` is held.
+    pub fn hold_direction(&mut self, direction: Direction) {
+        let player = &self.gameboard.players[0];
+        let (left, right) = (player.left_held, player.right_held);
+        let movement = [
+            (Action::MoveLeft, left, direction == Direction::Left),
+            (Action::MoveRight, right, direction == Direction::Right),
+        ];
+        // Releasing first keeps the other key from taking over after the press
+        for &(action, held, wanted) in &movement {
+            if held && !wanted {
+                self.handle_action(action, false);
+            }
+        }
+        for &(action, held, wanted) in &movement {
+            if wanted && !held {
+                self.handle_action(action, true);
+            }
+        }
+    }
+
+    /// Advances the simulation by one tick.
+    pub fn step(&mut self, dt: f64) {
+        self.tick += 1;
+
+        self.gameboard.update(dt);
+
+        if let Some(ref mut campaign) = self.campaign {
+            campaign.update(&mut self.gameboard);
+        }
+
+        if !self.score_recorded && self.is_game_finished() {
+            self.record_score();
+        }
+
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.record_tick(dt, self.gameboard.state_hash());
+        }
+        if let Some(mut buffer) = self.rewind.take() {
+            buffer.record_tick(self, dt);
+            self.rewind = Some(buffer);
+        }
+    }
+
+    /// Lets the AI, if any, move its paddle and runs the next tick.
+    pub fn advance(&mut self, dt: f64) {
+        // Goes through the inputs like a player would, so recordings replay without the AI
+        let (gameboard, tick) = (&self.gameboard, self.tick);
+        if let Some(input) = self.ai.as_mut().and_then(|ai| ai.think(gameboard, tick)) {
+            self.handle_input(input);
+        }
+        self.step(dt);
     }
 
     /// Handles events.
     pub fn event<E: GenericEvent>(&mut self, e: &E) {
-        use piston::input::{Button, ButtonState, Key, UpdateArgs};
+        use piston::input::{ButtonState, UpdateArgs};
+
+        if let (Some(mouse), Some([x, _])) = (&self.mouse, e.mouse_cursor_args()) {
+            let target = (x - mouse.board_position[0]) * self.gameboard.size / mouse.board_size;
+            self.handle_input(Input::PaddleTarget(target));
+        }
+
+        // Nobody is playing while the window is in the background
+        if e.focus_args() == Some(false) {
+            self.paused = true;
+        }
 
         if let Some(button_args) = e.button_args() {
             let pressed = button_args.state == ButtonState::Press;
-            if let Button::Keyboard(key) = button_args.button {
-                match key {
-                    Key::Left => {
-                        let dir = &mut self.gameboard.player.direction;
-                        if pressed {
-                            *dir = Direction::Left;
-                        } else {
-                            if *dir == Direction::Left {
-                                *dir = Direction::Idle;
-                            }
-                        }
-                    }
-                    Key::Right => {
-                        let dir = &mut self.gameboard.player.direction;
-                        if pressed {
-                            *dir = Direction::Right;
-                        } else {
-                            if *dir == Direction::Right {
-                                *dir = Direction::Idle;
-                            }
-                        }
+            if pressed && self.rebinding.is_some() {
+                self.rebind(button_args.button);
+            } else if let Some(action) = self.bindings.action(button_args.button) {
+                match action {
+                    Action::QuickSave if pressed => self.quick_save(),
+                    Action::QuickLoad if pressed => self.quick_load(),
+                    Action::Rewind => self.rewinding = pressed,
+                    Action::Pause if pressed => self.paused = !self.paused,
+                    Action::Step if pressed => self.step_requested = self.paused,
+                    Action::Rebind if pressed => {
+                        self.rebinding = Some(0);
+                        self.prompt_rebinding();
                     }
-                    _ => {}
+                    _ => self.handle_action(action, pressed),
                 }
             }
         }
-        
+
         if let Some(UpdateArgs { dt }) = e.update_args() {
             // println!("{}", dt);
-            self.gameboard.update(dt);
+            if self.rewinding {
+                self.rewind_step();
+            } else if !self.paused || self.step_requested {
+                self.step_requested = false;
+                self.advance(dt);
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piston::input::{ButtonArgs, ButtonState, Input as PistonInput, Key, Loop, Motion, UpdateArgs};
+
+    fn move_cursor(controller: &mut GameboardController, x: f64) {
+        controller.event(&Event::Input(PistonInput::Move(Motion::MouseCursor([x, 0.])), None));
+    }
+
+    fn update(controller: &mut GameboardController) {
+        controller.event(&Event::Loop(Loop::Update(UpdateArgs { dt: 1. / 120. })));
+    }
+
+    #[test]
+    fn test_paddle_follows_mouse() {
+        let mut controller = GameboardController::new(Gameboard::new(400.));
+        controller.mouse = Some(MouseControl {
+            board_position: [10., 10.],
+            board_size: 400.,
+        });
+        let paddle_x = |c: &GameboardController| c.gameboard.players[0].body.hitbox.position.x;
+        let max_step = controller.gameboard.physics.mouse_max_speed / 120.;
+
+        // Far to the right of the board, the paddle is limited in speed and stopped by the wall
+        move_cursor(&mut controller, 1000.);
+        for _ in 0..120 {
+            let before = paddle_x(&controller);
+            update(&mut controller);
+            assert!(paddle_x(&controller) - before <= max_step + 1e-9);
+        }
+        let hitbox = &controller.gameboard.players[0].body.hitbox;
+        assert!((hitbox.position.x + hitbox.dimension.x - 400.).abs() < 1e-9);
+
+        // The paddle's center ends up under the cursor
+        move_cursor(&mut controller, 210.);
+        for _ in 0..120 {
+            update(&mut controller);
+        }
+        assert!((paddle_x(&controller) - 150.).abs() < 1e-9);
+
+        // Keys take over until the mouse moves again
+        controller.handle_action(Action::MoveLeft, true);
+        update(&mut controller);
+        assert!(paddle_x(&controller) < 150.);
+    }
+
+    #[test]
+    fn test_coop_paddles_do_not_overlap() {
+        let blocks = crate::gameboard::default_blocks();
+        let mode = Mode::Coop(crate::gameboard::CoopLayout::SideBySide);
+        let mut controller =
+            GameboardController::new(Gameboard::with_mode(400., blocks, PhysicsSettings::default(), mode));
+        let edges = |c: &GameboardController| {
+            let [a, b] = [&c.gameboard.players[0].body.hitbox, &c.gameboard.players[1].body.hitbox];
+            (a.position.x + a.dimension.x, b.position.x)
+        };
+        let second_start = edges(&controller).1;
+
+        // Only the first paddle moves, so only it gets pushed back
+        controller.handle_action(Action::MoveRight, true);
+        for _ in 0..120 {
+            update(&mut controller);
+            let (right_of_first, left_of_second) = edges(&controller);
+            assert!(right_of_first <= left_of_second + 1e-9);
+        }
+        assert_eq!(edges(&controller).1, second_start);
+
+        controller.handle_action(Action::P2MoveLeft, true);
+        for _ in 0..120 {
+            update(&mut controller);
+            let (right_of_first, left_of_second) = edges(&controller);
+            assert!(right_of_first <= left_of_second + 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_versus_scoring() {
+        use crate::gameboard::{Block, GOAL_POINTS, STARTING_LIVES};
+        use crate::math::Vec2;
+
+        let block = Block::new(Vec2::new(180., 0.), Vec2::new(40., 10.), [255, 255, 0, 255]);
+        let board = Gameboard::with_mode(400., vec![block], PhysicsSettings::default(), Mode::Versus);
+        assert_eq!(board.blocks[0].body.hitbox.position.y, 195.);
+        let mut controller = GameboardController::new(board);
+        let score = |c: &GameboardController, i: usize| c.gameboard.players[i].score;
+
+        // The bottom paddle sends the ball up into the block, which counts for the bottom player
+        while !controller.gameboard.blocks.is_empty() {
+            update(&mut controller);
+            assert!(controller.tick < 600);
+        }
+        assert_eq!(controller.gameboard.ball.last_hit, Some(0));
+        assert_eq!((score(&controller, 0), score(&controller, 1)), (1, 0));
+
+        // The top paddle sends the ball back down
+        let ball = &mut controller.gameboard.ball.body;
+        ball.hitbox.position = Vec2::new(195., 60.);
+        ball.prev_position = ball.hitbox.position;
+        ball.velocity = Vec2::new(0., -300.);
+        while controller.gameboard.ball.last_hit != Some(1) {
+            update(&mut controller);
+            assert!(controller.tick < 600);
+        }
+        assert!(controller.gameboard.ball.body.velocity.y > 0.);
+
+        // With the bottom paddle out of the way the top player scores and the ball is served towards the bottom
+        controller.gameboard.players[0].body.hitbox.position.x = 0.;
+        while score(&controller, 1) == 0 {
+            update(&mut controller);
+            assert!(controller.tick < 1200);
+        }
+        assert_eq!((score(&controller, 0), score(&controller, 1)), (1, GOAL_POINTS));
+        assert_eq!(controller.gameboard.ball.last_hit, None);
+        assert!(controller.gameboard.ball.body.velocity.y > 0.);
+        assert_eq!(controller.gameboard.lives, STARTING_LIVES);
+        assert!(!controller.is_game_finished());
+    }
+
+    fn press(controller: &mut GameboardController, key: Key) {
+        let args = ButtonArgs {
+            state: ButtonState::Press,
+            button: Button::Keyboard(key),
+            scancode: None,
+        };
+        controller.event(&Event::Input(PistonInput::Button(args), None));
+    }
+
+    #[test]
+    fn test_pause_and_step() {
+        let mut controller = GameboardController::new(Gameboard::new(400.));
+
+        controller.event(&Event::Input(PistonInput::Focus(false), None));
+        assert!(controller.paused);
+        update(&mut controller);
+        assert_eq!(controller.tick, 0);
+
+        press(&mut controller, Key::Period);
+        update(&mut controller);
+        update(&mut controller);
+        assert_eq!(controller.tick, 1);
+
+        press(&mut controller, Key::P);
+        update(&mut controller);
+        assert_eq!(controller.tick, 2);
+        assert!(!controller.paused);
+    }
+}
@@ -2,12 +2,20 @@
 
 use graphics::types::Color;
 use graphics::{Context, Graphics};
+use serde::{Deserialize, Serialize};
 
+use crate::editor::EditorController;
 use crate::gameboard;
 use crate::gameboard_controller::GameboardController;
+use crate::highscore::{self, HighScores};
+use crate::replay_viewer::ReplayViewer;
 
 /// Stores gameboard view settings.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
 pub struct GameboardViewSettings {
+    /// Window size in pixels.
+    pub window_size: [u32; 2],
     /// Position from left-top corner.
     pub position: [f64; 2],
     /// Size of gameboard along horizontal and vertical edge.
@@ -22,6 +30,7 @@ impl GameboardViewSettings {
     /// Creates new gameboard view settings.
     pub fn new() -> GameboardViewSettings {
         GameboardViewSettings {
+            window_size: [512; 2],
             position: [10.0; 2],
             size: 400.0,
             background_color: [0.8, 0.8, 1.0, 0.5],
@@ -30,6 +39,12 @@ impl GameboardViewSettings {
     }
 }
 
+impl Default for GameboardViewSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Stores visual information about a gameboard.
 pub struct GameboardView {
     /// Stores gameboard view settings.
@@ -58,10 +73,8 @@ impl GameboardView {
         Rectangle::new(color).draw(rect, &c.draw_state, c.transform, g);
     }
 
-    /// Draw gameboard.
-    pub fn draw<G: Graphics>(&self, controller: &GameboardController, c: &Context, g: &mut G) {
+    fn draw_background<G: Graphics>(&self, c: &Context, g: &mut G) {
         use graphics::Rectangle;
-        let ref board = controller.gameboard;
 
         let ref settings = self.settings;
         let board_rect = [
@@ -73,24 +86,44 @@ impl GameboardView {
 
         // Draw board background.
         Rectangle::new(settings.background_color).draw(board_rect, &c.draw_state, c.transform, g);
+    }
 
-        let rect_of_hitbox = |hitbox: &gameboard::Rectangle| {
-            [
-                self.settings.position[0] + hitbox.position.x,
-                self.settings.position[1] + hitbox.position.y,
-                hitbox.dimension.x,
-                hitbox.dimension.y,
-            ]
-        };
-        self.draw_hitbox(
-            [1.0, 0.0, 0.0, 1.0],
-            rect_of_hitbox(&board.player.body.hitbox),
-            &c,
-            g,
-        );
+    fn rect_of_hitbox(&self, hitbox: &gameboard::Rectangle) -> [f64; 4] {
+        [
+            self.settings.position[0] + hitbox.position.x,
+            self.settings.position[1] + hitbox.position.y,
+            hitbox.dimension.x,
+            hitbox.dimension.y,
+        ]
+    }
+
+    /// Frames the board in red, shown while a changed file could not be loaded.
+    /// The view can not draw text, the parse message is printed to stdout and kept in `HotReload::error`.
+    pub fn draw_error_frame<G: Graphics>(&self, c: &Context, g: &mut G) {
+        let [x, y] = self.settings.position;
+        let size = self.settings.size;
+        let width = 4.;
+        let red = [1., 0., 0., 1.];
+
+        self.draw_hitbox(red, [x - width, y - width, size + 2. * width, width], c, g);
+        self.draw_hitbox(red, [x - width, y + size, size + 2. * width, width], c, g);
+        self.draw_hitbox(red, [x - width, y, width, size], c, g);
+        self.draw_hitbox(red, [x + size, y, width, size], c, g);
+    }
+
+    /// Draw gameboard.
+    pub fn draw<G: Graphics>(&self, controller: &GameboardController, c: &Context, g: &mut G) {
+        let board = &controller.gameboard;
+
+        self.draw_background(c, g);
+
+        // Player one is red, player two blue
+        for (player, color) in board.players.iter().zip([[1.0, 0.0, 0.0, 1.0], [0.0, 0.3, 1.0, 1.0]]) {
+            self.draw_hitbox(color, self.rect_of_hitbox(&player.body.hitbox), c, g);
+        }
         self.draw_hitbox(
             [1.0, 0.0, 0.0, 1.0],
-            rect_of_hitbox(&board.ball.body.hitbox),
+            self.rect_of_hitbox(&board.ball.body.hitbox),
             &c,
             g,
         );
@@ -98,10 +131,98 @@ impl GameboardView {
         for block in &board.blocks {
             self.draw_hitbox(
                 u8_color_to_f32_color(block.color),
-                rect_of_hitbox(&block.body.hitbox),
+                self.rect_of_hitbox(&block.body.hitbox),
                 &c,
                 g,
             );
         }
+
+        if controller.paused {
+            self.draw_pause_marker(c, g);
+        }
+        if controller.score_recorded {
+            if let Some(ref table) = controller.high_scores {
+                self.draw_high_scores(table, controller.score_rank, c, g);
+            }
+        }
+    }
+
+    /// The high score table as one bar per entry, as long as the score relative to the best one,
+    /// with the rank the game just reached in red. Co-op entries are marked blue in front of the bar.
+    fn draw_high_scores<G: Graphics>(&self, table: &HighScores, rank: Option<usize>, c: &Context, g: &mut G) {
+        let [x, y] = self.settings.position;
+        let size = self.settings.size;
+        let row = size / (2 * highscore::MAX_ENTRIES) as f64;
+        let top = y + (size - row * highscore::MAX_ENTRIES as f64) / 2.;
+        let best = table.entries().first().map_or(1, |e| e.score.max(1));
+
+        let mut background = self.settings.background_color;
+        background[3] = 1.;
+        self.draw_hitbox(background, [x + size / 8., top - row, size * 3. / 4., row * (highscore::MAX_ENTRIES + 2) as f64], c, g);
+
+        for (i, entry) in table.entries().iter().enumerate() {
+            let color = if rank == Some(i) { [1., 0., 0., 1.] } else { self.settings.border_color };
+            let width = size / 2. * f64::from(entry.score) / f64::from(best);
+            let bar_top = top + i as f64 * row + row / 4.;
+            self.draw_hitbox(color, [x + size / 4., bar_top, width.max(2.), row / 2.], c, g);
+            if let gameboard::Mode::Coop(_) = entry.players {
+                self.draw_hitbox([0.0, 0.3, 1.0, 1.0], [x + size / 4. - row, bar_top, row / 2., row / 2.], c, g);
+            }
+        }
+    }
+
+    /// Two bars in the top right corner of the board.
+    fn draw_pause_marker<G: Graphics>(&self, c: &Context, g: &mut G) {
+        let right = self.settings.position[0] + self.settings.size;
+        let top = self.settings.position[1];
+        self.draw_hitbox(self.settings.border_color, [right - 30., top + 10., 6., 20.], c, g);
+        self.draw_hitbox(self.settings.border_color, [right - 18., top + 10., 6., 20.], c, g);
+    }
+
+    /// Draw the level editor, or the running game while play-testing.
+    pub fn draw_editor<G: Graphics>(&self, controller: &EditorController, c: &Context, g: &mut G) {
+        if let Some(ref playtest) = controller.playtest {
+            return self.draw(playtest, c, g);
+        }
+
+        self.draw_background(c, g);
+
+        let editor = &controller.editor;
+        for spec in editor.level.blocks.iter() {
+            self.draw_hitbox(
+                u8_color_to_f32_color(spec.color),
+                self.rect_of_hitbox(&gameboard::Rectangle::new(spec.x, spec.y, spec.w, spec.h)),
+                c,
+                g,
+            );
+        }
+
+        // Show where a click would place a block
+        if let Some(spec) = editor.preview(controller.cursor) {
+            let mut color = u8_color_to_f32_color(spec.color);
+            color[3] = 0.4;
+            self.draw_hitbox(
+                color,
+                self.rect_of_hitbox(&gameboard::Rectangle::new(spec.x, spec.y, spec.w, spec.h)),
+                c,
+                g,
+            );
+        }
+    }
+
+    /// Draw a replay with its timeline and a pause marker.
+    pub fn draw_replay<G: Graphics>(&self, viewer: &ReplayViewer, c: &Context, g: &mut G) {
+        self.draw(&viewer.player.controller, c, g);
+
+        let [x, y, w, h] = viewer.timeline_rect();
+        self.draw_hitbox(self.settings.background_color, [x, y, w, h], c, g);
+        self.draw_hitbox(self.settings.border_color, [x, y, w * viewer.progress(), h], c, g);
+        if let Some(fraction) = viewer.divergence() {
+            self.draw_hitbox([1., 0., 0., 1.], [x + w * fraction - 1., y, 2., h], c, g);
+        }
+
+        if viewer.paused {
+            self.draw_pause_marker(c, g);
+        }
     }
 }
@@ -0,0 +1,276 @@
+//! Seeded procedural level generation.
+
+use crate::gameboard::{Block, BlockKind, Color};
+use crate::level::{Level, LevelPack};
+use crate::math::{Rng, Vec2};
+
+pub const PALETTE: [Color; 6] = [
+    [255, 64, 64, 255],
+    [255, 160, 0, 255],
+    [255, 255, 0, 255],
+    [64, 200, 64, 255],
+    [64, 128, 255, 255],
+    [160, 64, 255, 255],
+];
+
+const INDESTRUCTIBLE_COLOR: Color = [128, 128, 128, 255];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pattern {
+    /// Random cells mirrored around the vertical center line.
+    Symmetric,
+    /// A few blobs grown from random centers.
+    Clusters,
+    /// A full grid with rows, columns and single cells knocked out.
+    Gaps,
+    /// Picks one of the above from the seed.
+    Any,
+}
+
+pub struct GeneratorSettings {
+    pub pattern: Pattern,
+    pub columns: u32,
+    pub rows: u32,
+    /// Position of the top left block.
+    pub start_position: Vec2,
+    pub dimension: Vec2,
+    /// Fraction of grid cells which should contain a block.
+    pub density: f64,
+    pub multi_hit_chance: f64,
+    pub indestructible_chance: f64,
+    /// Upper bound of hits needed for multi-hit blocks.
+    pub max_hits: u32,
+}
+
+impl GeneratorSettings {
+    /// Settings for a difficulty between 0 (easy) and 1 (hard), using the default block grid.
+    pub fn for_difficulty(difficulty: f64) -> Self {
+        let difficulty = difficulty.clamp(0., 1.);
+        Self {
+            pattern: Pattern::Any,
+            columns: 8,
+            rows: 10,
+            start_position: Vec2::new(20., 20.),
+            dimension: Vec2::new(40., 10.),
+            density: 0.4 + 0.4 * difficulty,
+            multi_hit_chance: 0.4 * difficulty,
+            indestructible_chance: 0.15 * difficulty,
+            max_hits: 2 + (3. * difficulty) as u32,
+        }
+    }
+}
+
+fn fill_symmetric(rng: &mut Rng, settings: &GeneratorSettings, cells: &mut [Vec<bool>]) {
+    let half = settings.columns.div_ceil(2);
+    for row in cells.iter_mut() {
+        for j in 0..half {
+            let filled = rng.chance(settings.density);
+            row[j as usize] = filled;
+            row[(settings.columns - 1 - j) as usize] = filled;
+        }
+    }
+}
+
+fn fill_clusters(rng: &mut Rng, settings: &GeneratorSettings, cells: &mut [Vec<bool>]) {
+    let total = (settings.columns * settings.rows) as f64;
+    let target = (total * settings.density) as u32;
+    let cluster_count = rng.range(2, 5);
+    let mut filled = 0;
+
+    for _ in 0..cluster_count {
+        let mut i = rng.range(0, settings.rows);
+        let mut j = rng.range(0, settings.columns);
+
+        // Random walk from the cluster center
+        for _ in 0..target / cluster_count {
+            let cell = &mut cells[i as usize][j as usize];
+            if !*cell {
+                *cell = true;
+                filled += 1;
+            }
+            match rng.range(0, 4) {
+                0 if i > 0 => i -= 1,
+                1 if i + 1 < settings.rows => i += 1,
+                2 if j > 0 => j -= 1,
+                3 if j + 1 < settings.columns => j += 1,
+                _ => (),
+            }
+        }
+    }
+
+    if filled == 0 {
+        cells[0][0] = true;
+    }
+}
+
+fn fill_gaps(rng: &mut Rng, settings: &GeneratorSettings, cells: &mut [Vec<bool>]) {
+    for row in cells.iter_mut() {
+        for cell in row.iter_mut() {
+            *cell = true;
+        }
+    }
+
+    let gap_rows = rng.range(1, settings.rows / 3 + 2);
+    for _ in 0..gap_rows {
+        let i = rng.range(0, settings.rows) as usize;
+        for cell in cells[i].iter_mut() {
+            *cell = false;
+        }
+    }
+
+    let gap_columns = rng.range(0, settings.columns / 3 + 1);
+    for _ in 0..gap_columns {
+        let j = rng.range(0, settings.columns) as usize;
+        for row in cells.iter_mut() {
+            row[j] = false;
+        }
+    }
+
+    // Knock out single cells until the density is reached
+    for row in cells.iter_mut() {
+        for cell in row.iter_mut() {
+            if *cell && !rng.chance(settings.density) {
+                *cell = false;
+            }
+        }
+    }
+}
+
+fn pick_kind(rng: &mut Rng, settings: &GeneratorSettings) -> BlockKind {
+    let roll = rng.next_f64();
+    if roll < settings.indestructible_chance {
+        BlockKind::Indestructible
+    } else if roll < settings.indestructible_chance + settings.multi_hit_chance {
+        BlockKind::MultiHit(rng.range(2, settings.max_hits.max(2) + 1))
+    } else {
+        BlockKind::Normal
+    }
+}
+
+fn color_for(kind: BlockKind, row: u32) -> Color {
+    let base = PALETTE[row as usize % PALETTE.len()];
+    match kind {
+        BlockKind::Normal => base,
+        // Tougher blocks are darker
+        BlockKind::MultiHit(_) => [base[0] / 2, base[1] / 2, base[2] / 2, base[3]],
+        BlockKind::Indestructible => INDESTRUCTIBLE_COLOR,
+    }
+}
+
+/// Generates a block layout, the same seed and settings always produce the same blocks.
+pub fn generate(seed: u64, settings: &GeneratorSettings) -> Vec<Block> {
+    if settings.columns == 0 || settings.rows == 0 {
+        return Vec::new();
+    }
+
+    let mut rng = Rng::new(seed);
+    let mut cells = vec![vec![false; settings.columns as usize]; settings.rows as usize];
+
+    let pattern = match settings.pattern {
+        Pattern::Any => match rng.range(0, 3) {
+            0 => Pattern::Symmetric,
+            1 => Pattern::Clusters,
+            _ => Pattern::Gaps,
+        },
+        p => p,
+    };
+
+    match pattern {
+        Pattern::Symmetric => fill_symmetric(&mut rng, settings, &mut cells),
+        Pattern::Clusters => fill_clusters(&mut rng, settings, &mut cells),
+        _ => fill_gaps(&mut rng, settings, &mut cells),
+    }
+
+    let mut kinds = vec![vec![BlockKind::Normal; settings.columns as usize]; settings.rows as usize];
+    for (i, row) in kinds.iter_mut().enumerate() {
+        for j in 0..settings.columns as usize {
+            let mirrored = settings.columns as usize - 1 - j;
+            row[j] = if pattern == Pattern::Symmetric && mirrored < j {
+                row[mirrored]
+            } else {
+                pick_kind(&mut rng, settings)
+            };
+            if !cells[i][j] {
+                row[j] = BlockKind::Normal;
+            }
+        }
+    }
+
+    // A level without anything to destroy could never be cleared
+    let destructible = (0..settings.rows as usize)
+        .flat_map(|i| (0..settings.columns as usize).map(move |j| (i, j)))
+        .any(|(i, j)| cells[i][j] && kinds[i][j] != BlockKind::Indestructible);
+    if !destructible {
+        let center = (settings.columns / 2) as usize;
+        cells[0][center] = true;
+        kinds[0][center] = BlockKind::Normal;
+    }
+
+    let mut blocks = Vec::new();
+    for i in 0..settings.rows {
+        for j in 0..settings.columns {
+            if !cells[i as usize][j as usize] {
+                continue;
+            }
+            let kind = kinds[i as usize][j as usize];
+            let position = settings.start_position
+                + Vec2::new(j as f64 * (settings.dimension.x + 2.), i as f64 * (settings.dimension.y + 2.));
+            blocks.push(Block::new(position, settings.dimension, color_for(kind, i)).with_kind(kind));
+        }
+    }
+
+    blocks
+}
+
+/// A pack of `count` generated levels getting harder towards the end.
+pub fn generate_pack(seed: u64, count: u32) -> LevelPack {
+    let mut rng = Rng::new(seed);
+    let levels = (0..count)
+        .map(|i| {
+            let difficulty = if count > 1 { i as f64 / (count - 1) as f64 } else { 0.5 };
+            let blocks = generate(rng.next_u64(), &GeneratorSettings::for_difficulty(difficulty));
+            Level::from_blocks(&format!("Generated {}-{}", seed, i + 1), &blocks)
+        })
+        .collect();
+
+    LevelPack {
+        name: format!("Generated {}", seed),
+        levels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specs(blocks: &[Block]) -> Level {
+        Level::from_blocks("", blocks)
+    }
+
+    #[test]
+    fn test_same_seed_same_level() {
+        for &pattern in &[Pattern::Symmetric, Pattern::Clusters, Pattern::Gaps, Pattern::Any] {
+            let mut settings = GeneratorSettings::for_difficulty(0.7);
+            settings.pattern = pattern;
+
+            assert_eq!(specs(&generate(1234, &settings)), specs(&generate(1234, &settings)));
+        }
+    }
+
+    #[test]
+    fn test_different_seeds_differ() {
+        let settings = GeneratorSettings::for_difficulty(0.5);
+
+        assert_ne!(specs(&generate(1, &settings)), specs(&generate(2, &settings)));
+    }
+
+    #[test]
+    fn test_always_destructible() {
+        let mut settings = GeneratorSettings::for_difficulty(1.);
+        settings.indestructible_chance = 1.;
+
+        for seed in 0..20 {
+            assert!(generate(seed, &settings).iter().any(Block::is_destructible));
+        }
+    }
+}
@@ -0,0 +1,353 @@
+//! Reinforcement learning environment in the style of OpenAI Gym.
+
+use serde::{Deserialize, Serialize};
+
+use crate::gameboard::{Direction, Gameboard, Mode, PhysicsSettings, Rectangle};
+use crate::gameboard_controller::GameboardController;
+use crate::generator;
+use crate::headless::DT;
+use crate::level::Campaign;
+
+/// Levels in the generated pack an episode plays through.
+pub const EPISODE_LEVELS: u32 = 5;
+
+/// Gray values of the pixel observation.
+const BLOCK_SHADE: u8 = 96;
+const PADDLE_SHADE: u8 = 192;
+const BALL_SHADE: u8 = 255;
+
+/// Rewards given per step, the total of everything that happened during its ticks.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RewardSettings {
+    /// Per point scored, a point is a destroyed block.
+    pub score: f64,
+    /// Per ball bounced off the paddle.
+    pub paddle_hit: f64,
+    pub life_lost: f64,
+    pub level_cleared: f64,
+    /// Every tick, a negative value makes the agent hurry.
+    pub tick: f64,
+}
+
+impl Default for RewardSettings {
+    fn default() -> Self {
+        Self {
+            score: 1.,
+            paddle_hit: 0.1,
+            life_lost: -5.,
+            level_cleared: 10.,
+            tick: 0.,
+        }
+    }
+}
+
+/// What an observation contains.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ObservationKind {
+    /// See `Environment::features`.
+    Features,
+    /// A gray scale image of the board of the given width and height.
+    Pixels { width: usize, height: usize },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvSettings {
+    pub size: f64,
+    pub physics: PhysicsSettings,
+    pub observation: ObservationKind,
+    /// Columns and rows of the block occupancy grid in the feature vector.
+    pub grid: [usize; 2],
+    /// Simulation ticks per step, the action is held for all of them.
+    pub frame_skip: u32,
+    /// Steps after which an episode ends even if the game goes on.
+    pub max_steps: Option<u64>,
+    pub rewards: RewardSettings,
+}
+
+impl Default for EnvSettings {
+    fn default() -> Self {
+        Self {
+            size: 400.,
+            physics: PhysicsSettings::default(),
+            observation: ObservationKind::Features,
+            grid: [10, 10],
+            frame_skip: 4,
+            max_steps: Some(10_000),
+            rewards: RewardSettings::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Observation {
+    Features(Vec<f32>),
+    /// Row major, one byte per pixel.
+    Pixels(Vec<u8>),
+}
+
+/// A single player game an agent plays by moving the paddle left, right or not at all.
+pub struct Environment {
+    pub settings: EnvSettings,
+    pub controller: GameboardController,
+    steps: u64,
+}
+
+/// Range of cells along one axis that `start..start + length` overlaps, with `count` cells over `size`.
+fn cell_range(start: f64, length: f64, size: f64, count: usize) -> std::ops::Range<usize> {
+    let cell = size / count as f64;
+    let first = (start / cell).floor().max(0.) as usize;
+    let last = ((start + length) / cell).ceil().clamp(0., count as f64) as usize;
+    first.min(count)..last
+}
+
+fn fill(pixels: &mut [u8], width: usize, height: usize, size: f64, rect: &Rectangle, shade: u8) {
+    for y in cell_range(rect.position.y, rect.dimension.y, size, height) {
+        for x in cell_range(rect.position.x, rect.dimension.x, size, width) {
+            pixels[y * width + x] = pixels[y * width + x].max(shade);
+        }
+    }
+}
+
+impl Environment {
+    pub fn new(settings: EnvSettings) -> Self {
+        let controller = GameboardController::new(Gameboard::with_physics(settings.size, Vec::new(), settings.physics.clone()));
+        let mut env = Self {
+            settings,
+            controller,
+            steps: 0,
+        };
+        env.reset(0);
+        env
+    }
+
+    /// Starts a new episode on a pack generated from `seed`.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        let pack = generator::generate_pack(seed, EPISODE_LEVELS);
+        let campaign = Campaign::new(pack, 0).expect("Generated packs are never empty");
+        self.controller =
+            GameboardController::with_campaign(campaign, self.settings.size, self.settings.physics.clone(), Mode::Single);
+        self.steps = 0;
+        self.observe()
+    }
+
+    /// Holds `action` for `frame_skip` ticks, returns what the agent sees afterwards,
+    /// the reward for those ticks and whether the episode is over.
+    pub fn step(&mut self, action: Direction) -> (Observation, f64, bool) {
+        self.controller.hold_direction(action);
+
+        let rewards = &self.settings.rewards;
+        let mut reward = 0.;
+        for _ in 0..self.settings.frame_skip.max(1) {
+            if self.controller.is_game_finished() {
+                break;
+            }
+            let board = &self.controller.gameboard;
+            let (score, lives, falling) = (board.score, board.lives, board.ball.body.velocity.y > 0.);
+            // Finishing the last level leaves `current` as it is and sets `finished` instead
+            let level = self.controller.campaign.as_ref().map(|c| (c.current, c.finished));
+
+            self.controller.advance(DT);
+
+            let board = &self.controller.gameboard;
+            reward += rewards.tick;
+            reward += rewards.score * f64::from(board.score.saturating_sub(score));
+            reward += rewards.life_lost * f64::from(lives.saturating_sub(board.lives));
+            if self.controller.campaign.as_ref().map(|c| (c.current, c.finished)) != level {
+                reward += rewards.level_cleared;
+            }
+            // Blocks also turn the ball around, but not that close to the paddle
+            let paddle = &board.players[0].body.hitbox;
+            let ball = &board.ball.body;
+            if falling && ball.velocity.y < 0. && ball.hitbox.position.y + 2. * ball.hitbox.dimension.y >= paddle.position.y {
+                reward += rewards.paddle_hit;
+            }
+        }
+
+        self.steps += 1;
+        let done = self.controller.is_game_finished() || self.settings.max_steps.is_some_and(|max| self.steps >= max);
+        (self.observe(), reward, done)
+    }
+
+    pub fn observe(&self) -> Observation {
+        match self.settings.observation {
+            ObservationKind::Features => Observation::Features(self.features()),
+            ObservationKind::Pixels { width, height } => Observation::Pixels(self.pixels(width, height)),
+        }
+    }
+
+    /// Positions and velocities divided by the board size: paddle x, y and x velocity, ball x, y, x and y velocity,
+    /// followed by the block occupancy grid row by row, 1 where a destructible block overlaps the cell.
+    pub fn features(&self) -> Vec<f32> {
+        let board = &self.controller.gameboard;
+        let size = board.size;
+        let paddle = &board.players[0];
+        let paddle_velocity = match paddle.direction {
+            Direction::Left => -paddle.body.velocity.x.abs(),
+            Direction::Right => paddle.body.velocity.x.abs(),
+            Direction::Idle => 0.,
+        };
+        let ball = &board.ball.body;
+
+        let mut features: Vec<f32> = [
+            paddle.body.hitbox.position.x,
+            paddle.body.hitbox.position.y,
+            paddle_velocity,
+            ball.hitbox.position.x,
+            ball.hitbox.position.y,
+            ball.velocity.x,
+            ball.velocity.y,
+        ]
+        .iter()
+        .map(|v| (v / size) as f32)
+        .collect();
+
+        let [columns, rows] = self.settings.grid;
+        let mut grid = vec![0.; columns * rows];
+        for block in board.blocks.iter().filter(|b| b.is_destructible()) {
+            let hitbox = &block.body.hitbox;
+            for y in cell_range(hitbox.position.y, hitbox.dimension.y, size, rows) {
+                for x in cell_range(hitbox.position.x, hitbox.dimension.x, size, columns) {
+                    grid[y * columns + x] = 1.;
+                }
+            }
+        }
+        features.extend(grid);
+        features
+    }
+
+    /// The board drawn in gray, everything covering part of a pixel fills it so the ball never disappears.
+    pub fn pixels(&self, width: usize, height: usize) -> Vec<u8> {
+        let board = &self.controller.gameboard;
+        let mut pixels = vec![0; width * height];
+        for block in &board.blocks {
+            fill(&mut pixels, width, height, board.size, &block.body.hitbox, BLOCK_SHADE);
+        }
+        for player in &board.players {
+            fill(&mut pixels, width, height, board.size, &player.body.hitbox, PADDLE_SHADE);
+        }
+        fill(&mut pixels, width, height, board.size, &board.ball.body.hitbox, BALL_SHADE);
+        pixels
+    }
+}
+
+/// Moves the paddle towards the ball, a baseline to compare agents against.
+pub fn follow_ball(features: &[f32], paddle_width: f32) -> Direction {
+    let paddle_center = features[0] + paddle_width / 2.;
+    let ball_x = features[3];
+    if ball_x < paddle_center - paddle_width / 4. {
+        Direction::Left
+    } else if ball_x > paddle_center + paddle_width / 4. {
+        Direction::Right
+    } else {
+        Direction::Idle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboard::BlockKind;
+    use crate::level::{BlockSpec, Level, LevelPack};
+
+    fn play(env: &mut Environment, seed: u64, steps: usize) -> (Vec<Observation>, f64) {
+        let width = (env.settings.physics.paddle_size.x / env.settings.size) as f32;
+        let mut observations = vec![env.reset(seed)];
+        let mut total = 0.;
+        for _ in 0..steps {
+            let action = follow_ball(&env.features(), width);
+            let (observation, reward, done) = env.step(action);
+            observations.push(observation);
+            total += reward;
+            if done {
+                break;
+            }
+        }
+        (observations, total)
+    }
+
+    #[test]
+    fn test_episodes_are_reproducible() {
+        let mut env = Environment::new(EnvSettings::default());
+        let first = play(&mut env, 11, 500);
+        let second = play(&mut env, 11, 500);
+        assert!(first == second);
+        assert!(first.1 > 0.);
+
+        let other = play(&mut env, 12, 500);
+        assert!(first.0[0] != other.0[0]);
+    }
+
+    #[test]
+    fn test_observations() {
+        let mut env = Environment::new(EnvSettings {
+            observation: ObservationKind::Pixels { width: 40, height: 40 },
+            ..EnvSettings::default()
+        });
+        let features = env.features();
+        assert_eq!(features.len(), 7 + 100);
+        assert!(features[7..].contains(&1.));
+
+        // The ball is smaller than a pixel's ten board units and still shows up
+        let pixels = match env.reset(3) {
+            Observation::Pixels(pixels) => pixels,
+            Observation::Features(_) => panic!("expected pixels"),
+        };
+        assert_eq!(pixels.len(), 1600);
+        assert!(pixels.contains(&BALL_SHADE));
+        assert!(pixels.contains(&BLOCK_SHADE));
+        assert_eq!(pixels[35 * 40 + 20], PADDLE_SHADE);
+    }
+
+    #[test]
+    fn test_rewards() {
+        let rewards = RewardSettings {
+            score: 0.,
+            paddle_hit: 0.,
+            life_lost: -1.,
+            level_cleared: 0.,
+            tick: 0.,
+        };
+        let mut env = Environment::new(EnvSettings {
+            rewards,
+            max_steps: None,
+            ..EnvSettings::default()
+        });
+        env.reset(1);
+
+        // Standing still at the far left loses every ball
+        let mut total = 0.;
+        let mut done = false;
+        while !done {
+            let (_, reward, finished) = env.step(Direction::Left);
+            total += reward;
+            done = finished;
+        }
+        assert_eq!(total, -f64::from(crate::gameboard::STARTING_LIVES));
+
+        // Clearing the only level of a campaign finishes the episode with the level reward
+        env.settings.rewards = RewardSettings {
+            life_lost: 0.,
+            level_cleared: 1.,
+            ..env.settings.rewards.clone()
+        };
+        let block = BlockSpec { x: 140., y: 150., w: 60., h: 10., color: [0, 0, 0, 255], kind: BlockKind::Normal };
+        let pack = LevelPack {
+            name: String::new(),
+            levels: vec![Level { name: String::new(), blocks: vec![block] }],
+        };
+        env.controller =
+            GameboardController::with_campaign(Campaign::new(pack, 0).unwrap(), 400., PhysicsSettings::default(), Mode::Single);
+
+        let mut total = 0.;
+        let mut done = false;
+        while !done {
+            let (_, reward, finished) = env.step(Direction::Idle);
+            total += reward;
+            done = finished;
+        }
+        assert!(env.controller.campaign.as_ref().unwrap().finished);
+        assert_eq!(total, 1.);
+    }
+}
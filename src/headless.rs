@@ -0,0 +1,147 @@
+//! Running games without a window, for gameplay checks on machines without a display.
+
+use std::fmt;
+
+use crate::gameboard_controller::GameboardController;
+use crate::replay::ReplayPlayer;
+
+/// Time step of a headless tick, the same as the event loop's default 120 updates per second.
+pub const DT: f64 = 1. / 120.;
+
+/// Ticks a game runs without `--ticks`, ten minutes of play. An AI can keep a ball in play forever,
+/// so games always have a limit.
+pub const DEFAULT_MAX_TICKS: u64 = 10 * 60 * 120;
+
+/// How a headless run ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    GameOver,
+    /// The tick limit was reached first.
+    TickLimit,
+    /// A replay ran out of recorded ticks.
+    ReplayEnd,
+    /// A bot ended the game or disconnected.
+    Quit,
+}
+
+/// State at the end of a headless run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub outcome: Outcome,
+    pub ticks: u64,
+    pub score: u32,
+    pub lives: u32,
+    /// Destructible blocks still standing.
+    pub blocks_left: usize,
+    /// Level reached, 1 based.
+    pub level: usize,
+    /// First tick a replay stopped matching its recording.
+    pub divergence: Option<u64>,
+}
+
+impl Summary {
+    pub fn new(controller: &GameboardController, outcome: Outcome) -> Self {
+        let board = &controller.gameboard;
+        Self {
+            outcome,
+            ticks: controller.tick,
+            score: board.score,
+            lives: board.lives,
+            blocks_left: board.blocks.iter().filter(|b| b.is_destructible()).count(),
+            level: controller.campaign.as_ref().map_or(1, |c| c.current + 1),
+            divergence: None,
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let outcome = match self.outcome {
+            Outcome::GameOver => "game over",
+            Outcome::TickLimit => "tick limit reached",
+            Outcome::ReplayEnd => "replay finished",
+            Outcome::Quit => "bot quit",
+        };
+        writeln!(f, "Result:      {}", outcome)?;
+        writeln!(f, "Ticks:       {} ({:.1} s)", self.ticks, self.ticks as f64 * DT)?;
+        writeln!(f, "Score:       {}", self.score)?;
+        writeln!(f, "Lives:       {}", self.lives)?;
+        writeln!(f, "Level:       {}", self.level)?;
+        writeln!(f, "Blocks left: {}", self.blocks_left)?;
+        if let Some(tick) = self.divergence {
+            writeln!(f, "Diverged:    at tick {}", tick)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `controller` until the game is finished or `max_ticks` ticks ran, whichever is first.
+/// Without an AI set on the controller nobody moves the paddle.
+pub fn run(controller: &mut GameboardController, max_ticks: u64) -> Summary {
+    loop {
+        if controller.is_game_finished() {
+            return Summary::new(controller, Outcome::GameOver);
+        }
+        if controller.tick >= max_ticks {
+            return Summary::new(controller, Outcome::TickLimit);
+        }
+        controller.advance(DT);
+    }
+}
+
+/// Plays a replay until it ends, the game is finished or `max_ticks` ticks ran.
+pub fn run_replay(player: &mut ReplayPlayer, max_ticks: Option<u64>) -> Summary {
+    let outcome = loop {
+        if player.controller.is_game_finished() {
+            break Outcome::GameOver;
+        }
+        if max_ticks.is_some_and(|max| player.controller.tick >= max) {
+            break Outcome::TickLimit;
+        }
+        if !player.step() {
+            break Outcome::ReplayEnd;
+        }
+    };
+
+    Summary {
+        divergence: player.divergence(),
+        ..Summary::new(&player.controller, outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{Ai, AiSettings};
+    use crate::gameboard::Gameboard;
+    use crate::replay::Recorder;
+
+    #[test]
+    fn test_run_stops_at_game_over() {
+        // Nobody moves the paddle, so every ball is lost sooner or later
+        let mut controller = GameboardController::new(Gameboard::new(400.));
+        let summary = run(&mut controller, 1_000_000);
+
+        assert_eq!(summary.outcome, Outcome::GameOver);
+        assert_eq!(summary.lives, 0);
+        assert!(summary.ticks < 1_000_000);
+        assert_eq!(summary.score as usize, Gameboard::new(400.).blocks.len() - summary.blocks_left);
+    }
+
+    #[test]
+    fn test_replay_run_matches_ai_run() {
+        let mut controller = GameboardController::new(Gameboard::new(400.));
+        controller.ai = Some(Ai::new(AiSettings::default(), 3));
+        controller.recorder = Some(Recorder::new(&controller));
+        let summary = run(&mut controller, 2000);
+        assert_eq!(summary.outcome, Outcome::TickLimit);
+        assert_eq!(summary.ticks, 2000);
+
+        let replay = controller.recorder.take().unwrap().replay;
+        let mut player = ReplayPlayer::new(replay).unwrap();
+        let replayed = run_replay(&mut player, None);
+
+        assert_eq!(replayed.outcome, Outcome::ReplayEnd);
+        assert_eq!(replayed, Summary { outcome: Outcome::ReplayEnd, ..summary });
+    }
+}
@@ -0,0 +1,281 @@
+//! Persistent high-score table.
+
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::gameboard::{CoopLayout, Mode};
+
+/// Number of entries kept in the table.
+pub const MAX_ENTRIES: usize = 10;
+
+const TABLE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum HighScoreError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for HighScoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HighScoreError::Io(path, e) => write!(f, "could not access {}: {}", path.display(), e),
+            HighScoreError::Parse(path, e) => write!(f, "high score table {} is damaged: {}", path.display(), e),
+            HighScoreError::UnsupportedVersion(v) => write!(f, "high score table version {} is not supported", v),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub score: u32,
+    /// One based number of the last level played.
+    pub level: usize,
+    /// Seconds since the unix epoch.
+    pub date: u64,
+    /// Level pack or mode the score was achieved in.
+    pub mode: String,
+    /// Single player or co-op, tables written before it was stored only hold single player games.
+    #[serde(default)]
+    pub players: Mode,
+}
+
+impl Entry {
+    /// Creates an entry dated now.
+    pub fn new(name: &str, score: u32, level: usize, mode: &str, players: Mode) -> Self {
+        let date = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        Self {
+            name: name.to_string(),
+            score,
+            level,
+            date,
+            mode: mode.to_string(),
+            players,
+        }
+    }
+}
+
+/// Short name of a game mode for the table.
+pub fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Single => "single",
+        Mode::Coop(CoopLayout::SideBySide) => "co-op side",
+        Mode::Coop(CoopLayout::Stacked) => "co-op stacked",
+        Mode::Versus => "versus",
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TableFile {
+    version: u32,
+    entries: Vec<Entry>,
+}
+
+/// Formats seconds since the unix epoch as `YYYY-MM-DD`.
+pub fn format_date(seconds: u64) -> String {
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = (seconds / 86400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The name entries are recorded under, taken from the logged in user.
+pub fn player_name() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "Player".to_string())
+}
+
+/// Top scores sorted from best to worst, stored in a file.
+pub struct HighScores {
+    path: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl HighScores {
+    /// The table file inside the platform's user data directory.
+    pub fn default_path() -> Option<PathBuf> {
+        let data_dir = if cfg!(windows) {
+            env::var_os("APPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            env::var_os("HOME").map(|home| Path::new(&home).join("Library/Application Support"))
+        } else {
+            env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        };
+
+        data_dir.map(|dir| dir.join("rustout").join("highscores.json"))
+    }
+
+    /// Loads the table, a missing file is an empty table.
+    pub fn load(path: &Path) -> Result<Self, HighScoreError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(HighScoreError::Io(path.to_path_buf(), e)),
+        };
+
+        let mut entries = Vec::new();
+        if !text.is_empty() {
+            let file: TableFile =
+                serde_json::from_str(&text).map_err(|e| HighScoreError::Parse(path.to_path_buf(), e))?;
+            if file.version != TABLE_VERSION {
+                return Err(HighScoreError::UnsupportedVersion(file.version));
+            }
+            entries = file.entries;
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// True if a game with this score would make it into the table.
+    pub fn qualifies(&self, score: u32) -> bool {
+        self.entries.len() < MAX_ENTRIES || self.entries.last().is_none_or(|last| score > last.score)
+    }
+
+    /// Inserts the entry and returns its zero based rank, or `None` if it did not make the cut.
+    /// Equal scores rank below the ones achieved earlier.
+    pub fn insert(&mut self, entry: Entry) -> Option<usize> {
+        if !self.qualifies(entry.score) {
+            return None;
+        }
+
+        let rank = self.entries.iter().position(|e| e.score < entry.score).unwrap_or(self.entries.len());
+        self.entries.insert(rank, entry);
+        self.entries.truncate(MAX_ENTRIES);
+        Some(rank)
+    }
+
+    /// Writes the table to a temporary file and moves it over the old one,
+    /// so a crash midway leaves either the old or the new table behind.
+    pub fn save(&self) -> Result<(), HighScoreError> {
+        let io_error = |e| HighScoreError::Io(self.path.clone(), e);
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(io_error)?;
+        }
+
+        let file = TableFile {
+            version: TABLE_VERSION,
+            entries: self.entries.clone(),
+        };
+        let text = serde_json::to_string_pretty(&file).expect("High scores are always serializable");
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        let mut tmp = File::create(&tmp_path).map_err(io_error)?;
+        tmp.write_all(text.as_bytes()).map_err(io_error)?;
+        tmp.sync_all().map_err(io_error)?;
+        fs::rename(&tmp_path, &self.path).map_err(io_error)
+    }
+}
+
+impl fmt::Display for HighScores {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, e) in self.entries.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>2}. {:<16} {:>6}  level {:<3} {}  {:<13} {}",
+                i + 1,
+                e.name,
+                e.score,
+                e.level,
+                format_date(e.date),
+                mode_name(e.players),
+                e.mode
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, score: u32) -> Entry {
+        Entry {
+            name: name.to_string(),
+            score,
+            level: 1,
+            date: 0,
+            mode: String::new(),
+            players: Mode::Single,
+        }
+    }
+
+    #[test]
+    fn test_insert_keeps_best_entries() {
+        let mut table = HighScores { path: PathBuf::new(), entries: Vec::new() };
+
+        for score in 0..MAX_ENTRIES as u32 {
+            table.insert(entry("a", score * 10));
+        }
+        assert!(!table.qualifies(0));
+        assert_eq!(table.insert(entry("b", 0)), None);
+
+        assert_eq!(table.insert(entry("c", 45)), Some(5));
+        assert_eq!(table.insert(entry("d", 45)), Some(6));
+        assert_eq!(table.entries().len(), MAX_ENTRIES);
+        assert_eq!(table.entries()[0].score, 90);
+        assert_eq!(table.entries()[5].name, "c");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = env::temp_dir().join(format!("rustout_highscores_{}.json", std::process::id()));
+        let mut table = HighScores::load(&path).unwrap();
+        table.insert(entry("a", 10));
+        table.save().unwrap();
+
+        let loaded = HighScores::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.entries(), table.entries());
+    }
+
+    #[test]
+    fn test_game_mode_is_stored() {
+        let coop = Entry {
+            players: Mode::Coop(CoopLayout::Stacked),
+            ..entry("a", 10)
+        };
+        let table = HighScores { path: PathBuf::new(), entries: vec![coop.clone()] };
+        assert!(table.to_string().contains("co-op stacked"));
+
+        // Entries from before the mode was stored are single player games
+        let text = serde_json::to_string(&coop).unwrap().replace(",\"players\":{\"Coop\":\"Stacked\"}", "");
+        let old: Entry = serde_json::from_str(&text).unwrap();
+        assert_eq!(old.players, Mode::Single);
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_700_000_000), "2023-11-14");
+    }
+}
@@ -0,0 +1,262 @@
+//! Applying changes to the config and level files while the game runs.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::actions::Bindings;
+use crate::config::Config;
+use crate::gameboard_controller::{GameboardController, MouseControl};
+use crate::gameboard_view::GameboardViewSettings;
+use crate::level::LevelPack;
+use crate::rewind::RewindBuffer;
+
+/// How often the files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A file and the modification time it had when last looked at.
+struct WatchedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl WatchedFile {
+    fn new(path: PathBuf) -> Self {
+        let modified = Self::modified(&path);
+        Self { path, modified }
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    /// True if the file was changed, created or deleted since the last call.
+    fn changed(&mut self) -> bool {
+        let modified = Self::modified(&self.path);
+        let changed = modified != self.modified;
+        self.modified = modified;
+        changed
+    }
+}
+
+/// Watches the config and the level pack of a running game.
+pub struct HotReload {
+    config: WatchedFile,
+    /// The manifest followed by its level files.
+    pack: Vec<WatchedFile>,
+    next_check: Instant,
+    /// Bindings of the last loaded config, only a change to them replaces the ones rebound while playing.
+    bindings: Bindings,
+    /// Why the last changed file could not be applied, cleared by the next successful reload.
+    pub error: Option<String>,
+}
+
+impl HotReload {
+    /// `bindings` are the ones of the config the game started with.
+    pub fn new(config_path: PathBuf, manifest_path: Option<PathBuf>, bindings: Bindings) -> Self {
+        let mut reload = Self {
+            config: WatchedFile::new(config_path),
+            pack: Vec::new(),
+            next_check: Instant::now() + POLL_INTERVAL,
+            bindings,
+            error: None,
+        };
+        if let Some(path) = manifest_path {
+            reload.watch_pack(path);
+        }
+        reload
+    }
+
+    fn watch_pack(&mut self, manifest_path: PathBuf) {
+        // A broken manifest still gets watched, its levels are picked up once it is fixed
+        let levels = LevelPack::level_paths(&manifest_path).unwrap_or_default();
+        self.pack = Some(manifest_path).into_iter().chain(levels).map(WatchedFile::new).collect();
+    }
+
+    fn fail(&mut self, error: String) {
+        println!("{}", error);
+        self.error = Some(error);
+    }
+
+    /// Checks the files at most every `POLL_INTERVAL`.
+    pub fn poll(&mut self, controller: &mut GameboardController, view: &mut GameboardViewSettings) {
+        let now = Instant::now();
+        if now < self.next_check {
+            return;
+        }
+        self.next_check = now + POLL_INTERVAL;
+        self.check(controller, view);
+    }
+
+    /// Reloads every file changed since the last check.
+    pub fn check(&mut self, controller: &mut GameboardController, view: &mut GameboardViewSettings) {
+        if self.config.changed() {
+            match Config::load(&self.config.path) {
+                Ok(config) => {
+                    self.error = None;
+                    self.apply_config(config, controller, view);
+                }
+                Err(e) => self.fail(e.to_string()),
+            }
+        }
+
+        // Every file has to be checked so none of them reports the same change twice
+        let mut pack_changed = false;
+        for file in &mut self.pack {
+            pack_changed |= file.changed();
+        }
+        if pack_changed {
+            let manifest_path = self.pack[0].path.clone();
+            match LevelPack::load(&manifest_path) {
+                Ok(pack) => {
+                    self.error = None;
+                    self.apply_pack(pack, controller);
+                }
+                Err(e) => self.fail(e.to_string()),
+            }
+            // The manifest may list different files now
+            self.watch_pack(manifest_path);
+        }
+    }
+
+    fn apply_config(&mut self, config: Config, controller: &mut GameboardController, view: &mut GameboardViewSettings) {
+        // The window and the board keep their size until the game is restarted
+        if config.view.size != view.size || config.view.window_size != view.window_size {
+            println!("view.size and view.window_size take effect after a restart");
+        }
+        *view = GameboardViewSettings {
+            window_size: view.window_size,
+            size: view.size,
+            ..config.view
+        };
+        if config.bindings != self.bindings {
+            controller.bindings = config.bindings.clone();
+            self.bindings = config.bindings;
+        }
+        match controller.ai {
+            Some(ref mut ai) => ai.settings = config.ai,
+            None => controller.mouse = config.controls.mouse.then(|| MouseControl::for_view(view)),
+        }
+
+        // A recording only holds the inputs, so the physics have to stay as they were at the start
+        if controller.recorder.is_some() {
+            if config.physics != controller.gameboard.physics {
+                self.fail("Physics changes are ignored while recording".to_string());
+            }
+            return;
+        }
+        controller.gameboard.apply_physics(config.physics);
+        println!("Reloaded {}", self.config.path.display());
+    }
+
+    /// Replaces the pack and restarts the current level with the new blocks, keeping score and lives.
+    fn apply_pack(&mut self, pack: LevelPack, controller: &mut GameboardController) {
+        if controller.recorder.is_some() {
+            self.fail("Level changes are ignored while recording".to_string());
+            return;
+        }
+
+        let campaign = match controller.campaign {
+            Some(ref mut campaign) if !campaign.finished => campaign,
+            _ => return,
+        };
+
+        let board = &controller.gameboard;
+        campaign.current = campaign.current.min(pack.levels.len() - 1);
+        campaign.pack = pack;
+        campaign.score = board.score;
+        campaign.lives = board.lives;
+        controller.gameboard = campaign.make_board(board.size, board.physics.clone(), board.mode);
+
+        // Rewinding would bring back the old levels
+        if controller.rewind.is_some() {
+            controller.rewind = Some(RewindBuffer::new(controller));
+        }
+        println!("Reloaded {}", self.pack[0].path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::Action;
+    use crate::level::{Campaign, Level};
+    use piston::input::{Button, Key};
+    use std::env;
+    use std::fs::File;
+
+    /// Writes a file and moves its modification time forward, coarse file system clocks would miss the change.
+    fn write(path: &Path, text: &str, age: u64) {
+        fs::write(path, text).unwrap();
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 + age);
+        File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+    }
+
+    #[test]
+    fn test_config_changes_are_applied() {
+        let dir = env::temp_dir().join(format!("rustout_hot_reload_config_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rustout.toml");
+        write(&path, "", 0);
+
+        let mut controller = GameboardController::new(crate::Gameboard::new(400.));
+        let mut view = GameboardViewSettings::new();
+        let mut reload = HotReload::new(path.clone(), None, Bindings::default());
+        controller.bindings.bind(Action::Pause, Button::Keyboard(Key::O));
+
+        write(&path, "[physics]\npaddle_speed = 500.0\n[view]\nborder_color = [1.0, 0.0, 0.0, 1.0]\n", 1);
+        reload.check(&mut controller, &mut view);
+        assert_eq!(reload.error, None);
+        assert_eq!(controller.gameboard.players[0].body.velocity.x, 500.);
+        assert_eq!(view.border_color, [1., 0., 0., 1.]);
+        // Rebinding while playing survives changes to other settings
+        assert_eq!(controller.bindings.action(Button::Keyboard(Key::O)), Some(Action::Pause));
+
+        write(&path, "[physics]\npaddle_speed = \"fast\"\n", 2);
+        reload.check(&mut controller, &mut view);
+        assert!(reload.error.is_some());
+        assert_eq!(controller.gameboard.players[0].body.velocity.x, 500.);
+
+        write(&path, "[bindings]\npause = [{ Keyboard = \"Q\" }]\n", 3);
+        reload.check(&mut controller, &mut view);
+        assert_eq!(controller.bindings.action(Button::Keyboard(Key::O)), None);
+        assert_eq!(controller.bindings.action(Button::Keyboard(Key::Q)), Some(Action::Pause));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_level_changes_restart_level() {
+        let dir = env::temp_dir().join(format!("rustout_hot_reload_pack_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let manifest = dir.join("pack.json");
+        let pack = LevelPack {
+            name: "Test".to_string(),
+            levels: vec![Level::from_blocks("One", &crate::gameboard::default_blocks())],
+        };
+        pack.save(&manifest).unwrap();
+
+        let campaign = Campaign::new(pack, 0).unwrap();
+        let mut controller = GameboardController::with_campaign(campaign, 400., Default::default(), Default::default());
+        controller.gameboard.score = 5;
+        let mut view = GameboardViewSettings::new();
+        let mut reload = HotReload::new(dir.join("missing.toml"), Some(manifest.clone()), Bindings::default());
+
+        let level_path = &LevelPack::level_paths(&manifest).unwrap()[0];
+        let mut level = Level::load(level_path).unwrap();
+        level.blocks.truncate(3);
+        write(level_path, &serde_json::to_string(&level).unwrap(), 1);
+
+        reload.check(&mut controller, &mut view);
+        assert_eq!(reload.error, None);
+        assert_eq!(controller.gameboard.blocks.len(), 3);
+        assert_eq!(controller.gameboard.score, 5);
+
+        write(level_path, "{", 2);
+        reload.check(&mut controller, &mut view);
+        assert!(reload.error.is_some());
+        assert_eq!(controller.gameboard.blocks.len(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
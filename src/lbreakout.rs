@@ -0,0 +1,189 @@
+//! Importer for LBreakout2 level files.
+//!
+//! A file holds any number of levels, each looking like
+//!
+//! ```text
+//! Version:0102
+//! Author:Someone
+//! Title:Some Level
+//! Bricks:
+//! ..aaaaaaaaaa..
+//! ...
+//! Bonus:
+//! ..............
+//! ...
+//! ```
+//!
+//! Only the brick grid is imported, bonuses have no counterpart in this game.
+
+use std::fmt;
+
+use crate::gameboard::{BlockKind, Color};
+use crate::level::{BlockSpec, Level};
+
+/// Height of an LBreakout2 brick relative to its width.
+const BRICK_ASPECT: f64 = 0.5;
+
+const INDESTRUCTIBLE_COLOR: Color = [128, 128, 128, 255];
+
+#[derive(Debug, PartialEq)]
+pub enum ImportError {
+    /// A level header was not followed by a `Bricks:` section.
+    MissingBricks { line: usize },
+    /// A brick row is wider than the first row of its level.
+    RaggedRow { line: usize },
+    NoLevels,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::MissingBricks { line } => write!(f, "line {}: level has no Bricks: section", line),
+            ImportError::RaggedRow { line } => write!(f, "line {}: brick row is wider than the first row", line),
+            ImportError::NoLevels => write!(f, "file does not contain any levels"),
+        }
+    }
+}
+
+/// Maps a brick character to our block kind and color, `None` for empty cells.
+fn convert_brick(c: char) -> Option<(BlockKind, Color)> {
+    let kind = match c {
+        '.' | ' ' => return None,
+        // Walls and indestructible bricks
+        'E' | '#' => return Some((BlockKind::Indestructible, INDESTRUCTIBLE_COLOR)),
+        // Bricks needing several hits
+        'v' => BlockKind::MultiHit(2),
+        'w' => BlockKind::MultiHit(3),
+        'x' => BlockKind::MultiHit(4),
+        // Everything else (plain, explosive, growing, chaos...) becomes a plain block
+        _ => BlockKind::Normal,
+    };
+
+    // Spread the letters over the hue circle so different brick types stay distinguishable
+    let hue = (c as u32 % 24) as f64 / 24.;
+    let channel = |offset: f64| {
+        let t = ((hue + offset) * 6.).rem_euclid(6.);
+        let v = if t < 1. { t } else if t < 3. { 1. } else if t < 4. { 4. - t } else { 0. };
+        (55. + 200. * v) as u8
+    };
+    let mut color = [channel(2. / 6.), channel(0.), channel(4. / 6.), 255];
+    if let BlockKind::MultiHit(_) = kind {
+        color = [color[0] / 2, color[1] / 2, color[2] / 2, 255];
+    }
+
+    Some((kind, color))
+}
+
+fn import_level(title: &str, rows: &[(usize, &str)], size: f64) -> Result<Level, ImportError> {
+    let columns = rows.first().map_or(0, |(_, row)| row.chars().count());
+    let w = size / columns.max(1) as f64;
+    let h = w * BRICK_ASPECT;
+
+    let mut blocks = Vec::new();
+    for (i, &(line, row)) in rows.iter().enumerate() {
+        if row.chars().count() > columns {
+            return Err(ImportError::RaggedRow { line });
+        }
+        for (j, c) in row.chars().enumerate() {
+            if let Some((kind, color)) = convert_brick(c) {
+                blocks.push(BlockSpec { x: j as f64 * w, y: i as f64 * h, w, h, color, kind });
+            }
+        }
+    }
+
+    Ok(Level { name: title.to_string(), blocks })
+}
+
+/// Converts every level of an LBreakout2 level file, scaling the brick grid to the board width.
+pub fn import(text: &str, size: f64) -> Result<Vec<Level>, ImportError> {
+    let lines: Vec<(usize, &str)> = text.lines().map(str::trim_end).enumerate().map(|(i, l)| (i + 1, l)).collect();
+    let mut levels = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        if !lines[i].1.starts_with("Version:") {
+            i += 1;
+            continue;
+        }
+        let header_line = lines[i].0;
+        let mut title = format!("Level {}", levels.len() + 1);
+        i += 1;
+
+        // Header fields until the brick grid starts
+        while i < lines.len() && lines[i].1 != "Bricks:" {
+            if lines[i].1.starts_with("Version:") {
+                return Err(ImportError::MissingBricks { line: header_line });
+            }
+            if let Some(t) = lines[i].1.strip_prefix("Title:") {
+                title = t.trim().to_string();
+            }
+            i += 1;
+        }
+        if i == lines.len() {
+            return Err(ImportError::MissingBricks { line: header_line });
+        }
+        i += 1;
+
+        let start = i;
+        while i < lines.len() && lines[i].1 != "Bonus:" && !lines[i].1.starts_with("Version:") {
+            i += 1;
+        }
+        let rows: Vec<(usize, &str)> = lines[start..i].iter().copied().filter(|(_, l)| !l.is_empty()).collect();
+
+        levels.push(import_level(&title, &rows, size)?);
+    }
+
+    if levels.is_empty() {
+        return Err(ImportError::NoLevels);
+    }
+
+    Ok(levels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_LEVELS: &str = "Version:0102
+Author:Test
+Title:First
+Bricks:
+a..#
+.vx.
+Bonus:
+....
+....
+Version:0102
+Author:Test
+Title:Second
+Bricks:
+bbbb
+Bonus:
+....
+";
+
+    #[test]
+    fn test_import_levels() {
+        let levels = import(TWO_LEVELS, 400.).unwrap();
+
+        assert_eq!(levels.len(), 2);
+        assert_eq!(levels[0].name, "First");
+        assert_eq!(levels[1].blocks.len(), 4);
+
+        let kinds: Vec<BlockKind> = levels[0].blocks.iter().map(|b| b.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![BlockKind::Normal, BlockKind::Indestructible, BlockKind::MultiHit(2), BlockKind::MultiHit(4)]
+        );
+
+        let last = &levels[0].blocks[3];
+        assert_eq!((last.x, last.y, last.w, last.h), (200., 50., 100., 50.));
+    }
+
+    #[test]
+    fn test_import_errors() {
+        assert_eq!(import("", 400.), Err(ImportError::NoLevels));
+        assert_eq!(import("Version:0102\nTitle:x\n", 400.), Err(ImportError::MissingBricks { line: 1 }));
+        assert_eq!(import("Version:1\nBricks:\n..\n...\n", 400.), Err(ImportError::RaggedRow { line: 4 }));
+    }
+}
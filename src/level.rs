@@ -0,0 +1,353 @@
+//! Levels, level packs and campaign progression.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::gameboard::{
+    self, Alignment, Block, BlockKind, BlockLayout, Color, ColorSettings, Gameboard, LayoutSettings, Mode,
+    PhysicsSettings,
+};
+use crate::generator::PALETTE;
+use crate::math::Vec2;
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, serde_json::Error),
+    EmptyPack,
+    StartOutOfRange { start: usize, count: usize },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io(path, e) => write!(f, "could not access {}: {}", path.display(), e),
+            LevelError::Parse(path, e) => write!(f, "could not parse {}: {}", path.display(), e),
+            LevelError::EmptyPack => write!(f, "level pack does not contain any levels"),
+            LevelError::StartOutOfRange { start, count } => {
+                write!(f, "cannot start at level {}, pack only has {} levels", start + 1, count)
+            }
+        }
+    }
+}
+
+/// A single block as stored in a level file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BlockSpec {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+    pub color: Color,
+    #[serde(default)]
+    pub kind: BlockKind,
+}
+
+impl BlockSpec {
+    pub fn from_block(block: &Block) -> Self {
+        let hitbox = &block.body.hitbox;
+        Self {
+            x: hitbox.position.x,
+            y: hitbox.position.y,
+            w: hitbox.dimension.x,
+            h: hitbox.dimension.y,
+            color: block.color,
+            kind: block.kind,
+        }
+    }
+
+    pub fn to_block(&self) -> Block {
+        Block::new(Vec2::new(self.x, self.y), Vec2::new(self.w, self.h), self.color).with_kind(self.kind)
+    }
+}
+
+/// A block layout which can be turned into a fresh `Gameboard`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Level {
+    pub name: String,
+    pub blocks: Vec<BlockSpec>,
+}
+
+impl Level {
+    /// The level the game used to hard-code.
+    pub fn builtin() -> Self {
+        Self::from_blocks("Default", &gameboard::default_blocks())
+    }
+
+    pub fn from_blocks(name: &str, blocks: &[Block]) -> Self {
+        Self {
+            name: name.to_string(),
+            blocks: blocks.iter().map(BlockSpec::from_block).collect(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, LevelError> {
+        let text = fs::read_to_string(path).map_err(|e| LevelError::Io(path.to_path_buf(), e))?;
+        serde_json::from_str(&text).map_err(|e| LevelError::Parse(path.to_path_buf(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), LevelError> {
+        let text = serde_json::to_string_pretty(self).expect("Level is always serializable");
+        fs::write(path, text).map_err(|e| LevelError::Io(path.to_path_buf(), e))
+    }
+
+    pub fn make_blocks(&self) -> Vec<Block> {
+        self.blocks.iter().map(BlockSpec::to_block).collect()
+    }
+
+    /// Creates a fresh board for this level.
+    pub fn make_board(&self, size: f64, physics: PhysicsSettings, mode: Mode) -> Gameboard {
+        Gameboard::with_mode(size, self.make_blocks(), physics, mode)
+    }
+}
+
+/// On-disk form of a level pack, level paths are relative to the manifest.
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    name: String,
+    levels: Vec<PathBuf>,
+}
+
+/// An ordered list of levels.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LevelPack {
+    pub name: String,
+    pub levels: Vec<Level>,
+}
+
+const INVADER: [&str; 8] = [
+    "..#.....#..",
+    "...#...#...",
+    "..#######..",
+    ".##.###.##.",
+    "###########",
+    "#.#######.#",
+    "#.#.....#.#",
+    "...##.##...",
+];
+
+const HALF_HEART: [&str; 6] = [".##.", "####", "####", ".###", "..##", "...#"];
+
+fn mask_from_strings(rows: &[&str]) -> Vec<Vec<bool>> {
+    rows.iter().map(|row| row.chars().map(|c| c == '#').collect()).collect()
+}
+
+impl LevelPack {
+    /// A pack only containing the built-in level.
+    pub fn builtin() -> Self {
+        Self {
+            name: "Built-in".to_string(),
+            levels: vec![Level::builtin()],
+        }
+    }
+
+    /// The built-in level followed by one level per `BlockLayout` shape, for a board of the given size.
+    pub fn showcase(size: f64) -> Self {
+        let rainbow = || ColorSettings::PerRow((0..PALETTE.len() * 4).map(|i| PALETTE[i % PALETTE.len()]).collect());
+        let settings = LayoutSettings::new(size, rainbow());
+        let mut wide = LayoutSettings::new(size, rainbow());
+        wide.dimension = Vec2::new(30., 12.);
+        let mut left = LayoutSettings::new(size, rainbow());
+        left.alignment = Alignment::Left(20.);
+        let mut right = LayoutSettings::new(size, ColorSettings::Single(PALETTE[0]));
+        right.alignment = Alignment::Right(20.);
+        // Colored by column, every row reuses the last color row
+        let columns = LayoutSettings::new(size, ColorSettings::PerBlock(vec![PALETTE.iter().chain(PALETTE.iter()).copied().collect()]));
+
+        let shaped = [
+            ("Brick Wall", BlockLayout::brick_wall(8, 8, &settings)),
+            ("Pyramid", BlockLayout::pyramid(8, &left)),
+            ("Diamond", BlockLayout::diamond(5, &columns)),
+            ("Ellipse", BlockLayout::ellipse(9, 14, &settings)),
+            ("Invader", BlockLayout::from_mask(&mask_from_strings(&INVADER), &wide)),
+            ("Heart", BlockLayout::mirrored(&mask_from_strings(&HALF_HEART), &settings)),
+            ("Circle", BlockLayout::circle(140., &right)),
+        ];
+
+        let mut levels = vec![Level::builtin()];
+        levels.extend(shaped.iter().map(|(name, blocks)| Level::from_blocks(name, blocks)));
+
+        Self {
+            name: "Showcase".to_string(),
+            levels,
+        }
+    }
+
+    /// Parses a manifest without loading the levels it lists.
+    fn read_manifest(manifest_path: &Path) -> Result<Manifest, LevelError> {
+        let text = fs::read_to_string(manifest_path)
+            .map_err(|e| LevelError::Io(manifest_path.to_path_buf(), e))?;
+        serde_json::from_str(&text).map_err(|e| LevelError::Parse(manifest_path.to_path_buf(), e))
+    }
+
+    /// Paths of the level files listed in a manifest.
+    pub fn level_paths(manifest_path: &Path) -> Result<Vec<PathBuf>, LevelError> {
+        let base = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+        Ok(Self::read_manifest(manifest_path)?.levels.iter().map(|level| base.join(level)).collect())
+    }
+
+    /// Loads a manifest and every level it references.
+    pub fn load(manifest_path: &Path) -> Result<Self, LevelError> {
+        let manifest = Self::read_manifest(manifest_path)?;
+
+        let base = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+        let levels = manifest
+            .levels
+            .iter()
+            .map(|level| Level::load(&base.join(level)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if levels.is_empty() {
+            return Err(LevelError::EmptyPack);
+        }
+
+        Ok(Self { name: manifest.name, levels })
+    }
+
+    /// Writes the manifest and one file per level into the manifest's directory.
+    pub fn save(&self, manifest_path: &Path) -> Result<(), LevelError> {
+        let base = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+        let stem = manifest_path.file_stem().map_or("level".into(), |s| s.to_string_lossy());
+
+        let mut manifest = Manifest {
+            name: self.name.clone(),
+            levels: Vec::new(),
+        };
+        for (i, level) in self.levels.iter().enumerate() {
+            let file_name = PathBuf::from(format!("{}_{:03}.json", stem, i + 1));
+            level.save(&base.join(&file_name))?;
+            manifest.levels.push(file_name);
+        }
+
+        let text = serde_json::to_string_pretty(&manifest).expect("Manifest is always serializable");
+        fs::write(manifest_path, text).map_err(|e| LevelError::Io(manifest_path.to_path_buf(), e))
+    }
+}
+
+/// Walks through a level pack, keeping score and lives between levels.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Campaign {
+    pub pack: LevelPack,
+    /// Index of the level currently being played.
+    pub current: usize,
+    pub score: u32,
+    pub lives: u32,
+    pub finished: bool,
+}
+
+impl Campaign {
+    /// Starts a campaign at the given (zero based) level.
+    pub fn new(pack: LevelPack, start: usize) -> Result<Self, LevelError> {
+        if pack.levels.is_empty() {
+            return Err(LevelError::EmptyPack);
+        }
+        if start >= pack.levels.len() {
+            return Err(LevelError::StartOutOfRange { start, count: pack.levels.len() });
+        }
+
+        Ok(Self {
+            pack,
+            current: start,
+            score: 0,
+            lives: gameboard::STARTING_LIVES,
+            finished: false,
+        })
+    }
+
+    pub fn current_level(&self) -> &Level {
+        &self.pack.levels[self.current]
+    }
+
+    /// Creates a board for the current level carrying over score and lives.
+    pub fn make_board(&self, size: f64, physics: PhysicsSettings, mode: Mode) -> Gameboard {
+        let mut board = Gameboard::with_mode(size, self.current_level().make_blocks(), physics, mode);
+        board.score = self.score;
+        board.lives = self.lives;
+        board
+    }
+
+    /// Advances to the next level once `board` is cleared, replacing it with a fresh board.
+    /// Returns true if the level changed.
+    pub fn update(&mut self, board: &mut Gameboard) -> bool {
+        if self.finished || !board.is_cleared() {
+            return false;
+        }
+
+        self.score = board.score;
+        self.lives = board.lives;
+
+        if self.current + 1 >= self.pack.levels.len() {
+            self.finished = true;
+            return false;
+        }
+
+        self.current += 1;
+        let mut next = self.make_board(board.size, board.physics.clone(), board.mode);
+        // Versus points are kept for the whole match
+        for (next, player) in next.players.iter_mut().zip(&board.players) {
+            next.score = player.score;
+        }
+        *board = next;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack_of(count: usize) -> LevelPack {
+        LevelPack {
+            name: "Test".to_string(),
+            levels: (0..count)
+                .map(|i| Level {
+                    name: format!("Level {}", i),
+                    blocks: vec![BlockSpec {
+                        x: 20.,
+                        y: 20.,
+                        w: 40.,
+                        h: 10.,
+                        color: [0, 0, 0, 255],
+                        kind: BlockKind::Normal,
+                    }],
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_campaign_carries_score_and_lives() {
+        let mut campaign = Campaign::new(pack_of(2), 0).unwrap();
+        let mut board = campaign.make_board(400., PhysicsSettings::default(), Mode::Single);
+
+        board.blocks.clear();
+        board.score = 7;
+        board.lives = 2;
+
+        assert!(campaign.update(&mut board));
+        assert_eq!(campaign.current, 1);
+        assert_eq!(board.score, 7);
+        assert_eq!(board.lives, 2);
+        assert_eq!(board.blocks.len(), 1);
+    }
+
+    #[test]
+    fn test_campaign_finishes_after_last_level() {
+        let mut campaign = Campaign::new(pack_of(2), 1).unwrap();
+        let mut board = campaign.make_board(400., PhysicsSettings::default(), Mode::Single);
+
+        board.blocks.clear();
+
+        assert!(!campaign.update(&mut board));
+        assert!(campaign.finished);
+    }
+
+    #[test]
+    fn test_campaign_rejects_start_out_of_range() {
+        assert!(Campaign::new(pack_of(2), 2).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! Compact level codes which can be pasted into chat.
+//!
+//! A code is `RO` followed by the url safe base64 of
+//!
+//! ```text
+//! u8          version
+//! 6 x f32     grid origin x/y, grid step x/y, block width/height
+//! u8 u8       columns, rows
+//! u8          style count, then per style r g b a kind
+//! bitmap      one bit per grid cell, row major
+//! u8 ...      style index per block, only present with more than one style
+//! u16         Fletcher-16 checksum of everything before
+//! ```
+//!
+//! All numbers are little endian.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::gameboard::{BlockKind, Color};
+use crate::level::{BlockSpec, Level};
+
+const PREFIX: &str = "RO";
+const VERSION: u8 = 1;
+const EPSILON: f64 = 1e-6;
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+#[derive(Debug, PartialEq)]
+pub enum CodeError {
+    /// Blocks of different sizes can not be stored.
+    MixedDimensions { block: usize },
+    NotOnGrid { block: usize },
+    /// Two blocks sit in the same grid cell, a code stores at most one block per cell.
+    Overlapping { block: usize },
+    /// More than 256 rows, columns or styles.
+    TooLarge,
+    EmptyLevel,
+    MissingPrefix,
+    InvalidCharacter(char),
+    Truncated,
+    ChecksumMismatch,
+    UnsupportedVersion(u8),
+}
+
+impl fmt::Display for CodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodeError::MixedDimensions { block } => write!(f, "block {} differs in size from the first block", block),
+            CodeError::NotOnGrid { block } => write!(f, "block {} is not aligned to the grid of the other blocks", block),
+            CodeError::Overlapping { block } => write!(f, "block {} is in the same grid cell as another block", block),
+            CodeError::TooLarge => write!(f, "level has too many rows, columns or colors for a level code"),
+            CodeError::EmptyLevel => write!(f, "level has no blocks"),
+            CodeError::MissingPrefix => write!(f, "not a level code, codes start with {}", PREFIX),
+            CodeError::InvalidCharacter(c) => write!(f, "level code contains invalid character {:?}", c),
+            CodeError::Truncated => write!(f, "level code is incomplete"),
+            CodeError::ChecksumMismatch => write!(f, "level code is corrupted"),
+            CodeError::UnsupportedVersion(v) => {
+                write!(f, "level code version {} is not supported, this game reads version {}", v, VERSION)
+            }
+        }
+    }
+}
+
+fn fletcher16(data: &[u8]) -> u16 {
+    let (mut a, mut b) = (0u16, 0u16);
+    for &byte in data {
+        a = (a + u16::from(byte)) % 255;
+        b = (b + a) % 255;
+    }
+    (b << 8) | a
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    out
+}
+
+fn base64_decode(text: &str) -> Result<Vec<u8>, CodeError> {
+    let mut out = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in text.chars() {
+        let value = ALPHABET.iter().position(|&a| a as char == c).ok_or(CodeError::InvalidCharacter(c))?;
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+fn kind_to_byte(kind: BlockKind) -> Result<u8, CodeError> {
+    match kind {
+        BlockKind::Normal => Ok(0),
+        BlockKind::MultiHit(n) if (1..255).contains(&n) => Ok(n as u8),
+        BlockKind::MultiHit(_) => Err(CodeError::TooLarge),
+        BlockKind::Indestructible => Ok(255),
+    }
+}
+
+fn byte_to_kind(byte: u8) -> BlockKind {
+    match byte {
+        0 => BlockKind::Normal,
+        255 => BlockKind::Indestructible,
+        n => BlockKind::MultiHit(u32::from(n)),
+    }
+}
+
+// Smallest distance between distinct coordinates, the grid step along one axis
+fn grid_step(mut values: Vec<f64>, fallback: f64) -> f64 {
+    values.sort_by(|a, b| a.partial_cmp(b).expect("block positions are never NaN"));
+    let step = values
+        .windows(2)
+        .map(|w| w[1] - w[0])
+        .filter(|&d| d > EPSILON)
+        .fold(f64::INFINITY, f64::min);
+
+    if step.is_finite() {
+        step
+    } else {
+        fallback
+    }
+}
+
+fn to_cell(value: f64, origin: f64, step: f64, block: usize) -> Result<usize, CodeError> {
+    let cell = ((value - origin) / step).round();
+    if (origin + cell * step - value).abs() > EPSILON * value.abs().max(1.) {
+        return Err(CodeError::NotOnGrid { block });
+    }
+    if cell > 255. {
+        return Err(CodeError::TooLarge);
+    }
+    Ok(cell as usize)
+}
+
+/// Encodes a level whose blocks share one size and sit on a common grid.
+pub fn encode(level: &Level) -> Result<String, CodeError> {
+    let blocks = &level.blocks;
+    let first = blocks.first().ok_or(CodeError::EmptyLevel)?;
+    for (i, spec) in blocks.iter().enumerate() {
+        if (spec.w - first.w).abs() > EPSILON || (spec.h - first.h).abs() > EPSILON {
+            return Err(CodeError::MixedDimensions { block: i });
+        }
+    }
+
+    let origin = (
+        blocks.iter().map(|b| b.x).fold(f64::INFINITY, f64::min),
+        blocks.iter().map(|b| b.y).fold(f64::INFINITY, f64::min),
+    );
+    let step = (
+        grid_step(blocks.iter().map(|b| b.x).collect(), first.w),
+        grid_step(blocks.iter().map(|b| b.y).collect(), first.h),
+    );
+
+    let mut styles: Vec<(Color, BlockKind)> = Vec::new();
+    let mut cells = Vec::new();
+    let mut occupied = BTreeSet::new();
+    for (i, spec) in blocks.iter().enumerate() {
+        let cell = (to_cell(spec.x, origin.0, step.0, i)?, to_cell(spec.y, origin.1, step.1, i)?);
+        if !occupied.insert(cell) {
+            return Err(CodeError::Overlapping { block: i });
+        }
+        let style = (spec.color, spec.kind);
+        let style_index = match styles.iter().position(|&s| s == style) {
+            Some(index) => index,
+            None => {
+                styles.push(style);
+                styles.len() - 1
+            }
+        };
+        cells.push((cell.1, cell.0, style_index));
+    }
+    if styles.len() > 256 {
+        return Err(CodeError::TooLarge);
+    }
+
+    // Row major so the bitmap and the style indices line up
+    cells.sort();
+    let columns = cells.iter().map(|c| c.1).max().unwrap_or(0) + 1;
+    let rows = cells.iter().map(|c| c.0).max().unwrap_or(0) + 1;
+
+    let mut data = vec![VERSION];
+    for &value in &[origin.0, origin.1, step.0, step.1, first.w, first.h] {
+        data.extend_from_slice(&(value as f32).to_le_bytes());
+    }
+    data.extend_from_slice(&[(columns - 1) as u8, (rows - 1) as u8, (styles.len() - 1) as u8]);
+    for &(color, kind) in &styles {
+        data.extend_from_slice(&color);
+        data.push(kind_to_byte(kind)?);
+    }
+
+    let mut bitmap = vec![0u8; (columns * rows).div_ceil(8)];
+    for &(row, column, _) in &cells {
+        let bit = row * columns + column;
+        bitmap[bit / 8] |= 1 << (bit % 8);
+    }
+    data.extend_from_slice(&bitmap);
+    if styles.len() > 1 {
+        data.extend(cells.iter().map(|&(_, _, style)| style as u8));
+    }
+
+    let checksum = fletcher16(&data);
+    data.extend_from_slice(&checksum.to_le_bytes());
+
+    Ok(format!("{}{}", PREFIX, base64_encode(&data)))
+}
+
+/// Reads bytes front to back, failing with `Truncated` at the end.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], CodeError> {
+        if self.data.len() < count {
+            return Err(CodeError::Truncated);
+        }
+        let (head, tail) = self.data.split_at(count);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, CodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn f32(&mut self) -> Result<f64, CodeError> {
+        let bytes = self.take(4)?;
+        Ok(f64::from(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])))
+    }
+}
+
+/// Decodes a level code into a level with the given name.
+pub fn decode(code: &str, name: &str) -> Result<Level, CodeError> {
+    let body = code.trim().strip_prefix(PREFIX).ok_or(CodeError::MissingPrefix)?;
+    let data = base64_decode(body)?;
+
+    // Check the version first, a newer format might not even use the same checksum
+    let mut reader = Reader { data: &data };
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(CodeError::UnsupportedVersion(version));
+    }
+
+    // The header tells how long the code has to be, so a cut off code is reported as such before the checksum
+    let (ox, oy, sx, sy, w, h) = (reader.f32()?, reader.f32()?, reader.f32()?, reader.f32()?, reader.f32()?, reader.f32()?);
+    let columns = reader.u8()? as usize + 1;
+    let rows = reader.u8()? as usize + 1;
+    let style_count = reader.u8()? as usize + 1;
+
+    let mut styles = Vec::new();
+    for _ in 0..style_count {
+        let style = reader.take(5)?;
+        styles.push(([style[0], style[1], style[2], style[3]], byte_to_kind(style[4])));
+    }
+
+    let bitmap = reader.take((columns * rows).div_ceil(8))?;
+    let occupied: BTreeSet<usize> = (0..columns * rows).filter(|&bit| bitmap[bit / 8] & (1 << (bit % 8)) != 0).collect();
+    let style_indices = if style_count > 1 { reader.take(occupied.len())? } else { &[] };
+
+    let payload_length = data.len() - reader.data.len();
+    let checksum = reader.take(2)?;
+    if !reader.data.is_empty() || fletcher16(&data[..payload_length]) != u16::from_le_bytes([checksum[0], checksum[1]]) {
+        return Err(CodeError::ChecksumMismatch);
+    }
+
+    let mut blocks = Vec::new();
+    for (i, bit) in occupied.into_iter().enumerate() {
+        let style_index = style_indices.get(i).map_or(0, |&index| index as usize);
+        let &(color, kind) = styles.get(style_index).ok_or(CodeError::ChecksumMismatch)?;
+        blocks.push(BlockSpec {
+            x: ox + (bit % columns) as f64 * sx,
+            y: oy + (bit / columns) as f64 * sy,
+            w,
+            h,
+            color,
+            kind,
+        });
+    }
+
+    Ok(Level { name: name.to_string(), blocks })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::LevelPack;
+
+    fn sorted(level: &Level) -> Vec<BlockSpec> {
+        let mut blocks = level.blocks.clone();
+        blocks.sort_by(|a, b| (a.y, a.x).partial_cmp(&(b.y, b.x)).unwrap());
+        blocks
+    }
+
+    #[test]
+    fn test_roundtrip_showcase_levels() {
+        for level in LevelPack::showcase(400.).levels.iter() {
+            let code = encode(level).unwrap();
+            let decoded = decode(&code, &level.name).unwrap();
+
+            assert_eq!(sorted(&decoded), sorted(level), "{}", level.name);
+        }
+    }
+
+    #[test]
+    fn test_default_level_code_is_short() {
+        assert!(encode(&Level::builtin()).unwrap().len() < 80);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let code = encode(&Level::builtin()).unwrap();
+
+        let mut corrupted: Vec<char> = code.chars().collect();
+        corrupted[12] = if corrupted[12] == 'A' { 'B' } else { 'A' };
+        let corrupted: String = corrupted.into_iter().collect();
+
+        assert_eq!(decode(&corrupted, ""), Err(CodeError::ChecksumMismatch));
+        assert_eq!(decode(&code[..code.len() / 2], ""), Err(CodeError::Truncated));
+        assert_eq!(decode(&code[..code.len() - 3], ""), Err(CodeError::Truncated));
+        assert_eq!(decode(&format!("{}AAAA", code), ""), Err(CodeError::ChecksumMismatch));
+        assert_eq!(decode("hello", ""), Err(CodeError::MissingPrefix));
+        assert_eq!(decode("RO!!", ""), Err(CodeError::InvalidCharacter('!')));
+        assert_eq!(decode(&format!("RO{}", base64_encode(&[2, 0, 0, 0])), ""), Err(CodeError::UnsupportedVersion(2)));
+    }
+
+    #[test]
+    fn test_off_grid_blocks_are_rejected() {
+        let spec = |x| BlockSpec { x, y: 0., w: 10., h: 10., color: [0, 0, 0, 255], kind: BlockKind::Normal };
+        let level = Level { name: String::new(), blocks: vec![spec(0.), spec(10.), spec(25.)] };
+
+        assert_eq!(encode(&level), Err(CodeError::NotOnGrid { block: 2 }));
+    }
+
+    #[test]
+    fn test_overlapping_blocks_are_rejected() {
+        let spec = |x, color| BlockSpec { x, y: 0., w: 10., h: 10., color, kind: BlockKind::Normal };
+        let level = Level {
+            name: String::new(),
+            blocks: vec![spec(0., [0, 0, 0, 255]), spec(10., [0, 0, 0, 255]), spec(10., [255, 0, 0, 255])],
+        };
+
+        assert_eq!(encode(&level), Err(CodeError::Overlapping { block: 2 }));
+    }
+}
@@ -9,35 +9,196 @@ use opengl_graphics::{GlGraphics, OpenGL};
 
 use glutin_window::GlutinWindow;
 
+use std::env;
+use std::fs;
+use std::io::{self, BufReader};
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 pub use crate::gameboard::Gameboard;
 pub use crate::gameboard_controller::GameboardController;
 pub use crate::gameboard_view::{GameboardView, GameboardViewSettings};
 
+mod actions;
+mod ai;
+mod batch;
+mod bot;
+mod config;
+mod editor;
 mod gameboard;
 mod gameboard_controller;
 mod gameboard_view;
+mod generator;
+mod gym;
+mod headless;
+mod highscore;
+mod hot_reload;
+mod lbreakout;
+mod level;
+mod level_code;
 mod math;
+mod replay;
+mod replay_viewer;
+mod rewind;
+mod save;
+mod validator;
 
-fn main() {
+use crate::ai::Ai;
+use crate::config::Config;
+use crate::editor::{Editor, EditorController};
+use crate::gameboard::{CoopLayout, Mode, PhysicsSettings};
+use crate::batch::BatchEnv;
+use crate::bot::BotSession;
+use crate::gym::EnvSettings;
+use crate::highscore::HighScores;
+use crate::hot_reload::HotReload;
+use crate::level::{Campaign, Level, LevelPack};
+use crate::replay::{Recorder, Replay, ReplayPlayer};
+use crate::replay_viewer::ReplayViewer;
+use crate::rewind::RewindBuffer;
+
+/// Number of levels in a pack created with `--seed`.
+const GENERATED_PACK_LENGTH: u32 = 10;
+
+/// Options of a regular game.
+struct GameArgs {
+    campaign: Campaign,
+    mode: Mode,
+    /// Manifest the levels were loaded from, watched for changes.
+    pack_path: Option<PathBuf>,
+    /// Where to write a replay of the session.
+    record: Option<PathBuf>,
+    /// The computer plays the first paddle.
+    ai: bool,
+}
+
+/// Builds the campaign from `--pack <manifest>`, `--showcase`, `--seed <n>` or `--code <level code>`, and `--level <n>` (1 based),
+/// `--coop side|stacked` adds a second paddle, `--versus` puts the second player at the top,
+/// `--ai` lets the computer play the first paddle and `--record <replay file>` records the session.
+fn game_args_from_args(args: &[String], size: f64) -> Result<GameArgs, String> {
+    let mut pack = LevelPack::builtin();
+    let mut start = 0;
+    let mut record = None;
+    let mut pack_path = None;
+    let mut mode = Mode::Single;
+    let mut ai = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--pack" => {
+                let path = iter.next().ok_or("--pack expects a manifest path")?;
+                pack = LevelPack::load(Path::new(path)).map_err(|e| e.to_string())?;
+                pack_path = Some(PathBuf::from(path));
+            }
+            "--showcase" => {
+                pack = LevelPack::showcase(size);
+                pack_path = None;
+            }
+            "--seed" => {
+                let seed = iter.next().ok_or("--seed expects a number")?;
+                let seed = seed.parse::<u64>().map_err(|_| format!("invalid seed: {}", seed))?;
+                pack = generator::generate_pack(seed, GENERATED_PACK_LENGTH);
+                pack_path = None;
+            }
+            "--code" => {
+                let code = iter.next().ok_or("--code expects a level code")?;
+                let level = level_code::decode(code, "Shared level").map_err(|e| e.to_string())?;
+                pack = LevelPack {
+                    name: "Shared level".to_string(),
+                    levels: vec![level],
+                };
+                pack_path = None;
+            }
+            "--level" => {
+                let level = iter.next().ok_or("--level expects a level number")?;
+                start = match level.parse::<usize>() {
+                    Ok(n) if n > 0 => n - 1,
+                    _ => return Err(format!("invalid level number: {}", level)),
+                };
+            }
+            "--coop" => {
+                let layout = iter.next().ok_or("--coop expects side or stacked")?;
+                mode = match layout.as_str() {
+                    "side" => Mode::Coop(CoopLayout::SideBySide),
+                    "stacked" => Mode::Coop(CoopLayout::Stacked),
+                    _ => return Err(format!("invalid co-op layout: {}", layout)),
+                };
+            }
+            "--versus" => mode = Mode::Versus,
+            "--ai" => ai = true,
+            "--record" => {
+                let path = iter.next().ok_or("--record expects a replay path")?;
+                record = Some(PathBuf::from(path));
+            }
+            other => return Err(format!("unknown argument: {}", other)),
+        }
+    }
+
+    Ok(GameArgs {
+        campaign: Campaign::new(pack, start).map_err(|e| e.to_string())?,
+        mode,
+        pack_path,
+        record,
+        ai,
+    })
+}
+
+fn open_window(size: [u32; 2]) -> (GlutinWindow, GlGraphics) {
     let opengl = OpenGL::V3_2;
-    let settings = WindowSettings::new("Sudoku", [512; 2])
+    let settings = WindowSettings::new("Sudoku", size)
         // .samples(8)
         // .vsync(true)
         .graphics_api(opengl)
         .exit_on_esc(true);
-    let mut window: GlutinWindow = settings.build().expect("Could not create window");
+    let window: GlutinWindow = settings.build().expect("Could not create window");
+
+    (window, GlGraphics::new(opengl))
+}
+
+fn load_high_scores() -> Option<HighScores> {
+    let path = HighScores::default_path()?;
+    match HighScores::load(&path) {
+        Ok(table) => Some(table),
+        Err(e) => {
+            println!("{}", e);
+            None
+        }
+    }
+}
+
+fn run_game(
+    mut gameboard_controller: GameboardController,
+    record: Option<PathBuf>,
+    config: &Config,
+    pack_path: Option<PathBuf>,
+) {
+    gameboard_controller.high_scores = load_high_scores();
+    gameboard_controller.bindings = config.bindings.clone();
+    // The mouse would fight the AI over the paddle
+    if gameboard_controller.ai.is_none() {
+        gameboard_controller.mouse = config.mouse_control();
+    }
+    // Rewinding would make the recording impossible to replay
+    if record.is_some() {
+        gameboard_controller.recorder = Some(Recorder::new(&gameboard_controller));
+    } else {
+        gameboard_controller.rewind = Some(RewindBuffer::new(&gameboard_controller));
+    }
+
+    let (mut window, mut gl) = open_window(config.view.window_size);
 
     let mut event_settings = EventSettings::new();
     // event_settings.set_max_fps(60);
     // event_settings.set_ups(60);
 
     let mut events = Events::new(event_settings);
-    let mut gl = GlGraphics::new(opengl);
 
-    let gameboard_view_settings = GameboardViewSettings::new();
-    let mut gameboard_controller =
-        GameboardController::new(Gameboard::new(gameboard_view_settings.size));
-    let gameboard_view = GameboardView::new(gameboard_view_settings);
+    let mut gameboard_view = GameboardView::new(config.view.clone());
+    let mut hot_reload = HotReload::new(PathBuf::from(config::CONFIG_PATH), pack_path, config.bindings.clone());
 
     while let Some(e) = events.next(&mut window) {
         
@@ -47,11 +208,423 @@ fn main() {
 
                 clear([1.0; 4], g);
                 gameboard_view.draw(&gameboard_controller, &c, g);
+                if hot_reload.error.is_some() {
+                    gameboard_view.draw_error_frame(&c, g);
+                }
             });
         }
         else {
             println!("print because game is slow otherwise");
             gameboard_controller.event(&e);
+            hot_reload.poll(&mut gameboard_controller, &mut gameboard_view.settings);
+        }
+    }
+
+    if let (Some(path), Some(recorder)) = (record, gameboard_controller.recorder) {
+        match recorder.replay.save(&path) {
+            Ok(()) => println!("Saved replay to {}", path.display()),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// Runs a game without a window and prints how it went. Takes `--ticks <n>` to stop early, games played by the AI
+/// stop after `headless::DEFAULT_MAX_TICKS` without it. Also takes either
+/// `--replay <replay file>` to play back a recording or the options of a regular game played by the AI,
+/// with `--ai-seed <n>` to make its mistakes reproducible.
+fn run_headless(args: &[String], config: &Config) -> Result<(), String> {
+    let mut max_ticks = None;
+    let mut replay = None;
+    let mut seed = 0;
+    let mut game_args = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--ticks" => {
+                let ticks = iter.next().ok_or("--ticks expects a number")?;
+                max_ticks = Some(ticks.parse::<u64>().map_err(|_| format!("invalid tick count: {}", ticks))?);
+            }
+            "--replay" => replay = Some(PathBuf::from(iter.next().ok_or("--replay expects a replay path")?)),
+            "--ai-seed" => {
+                let value = iter.next().ok_or("--ai-seed expects a number")?;
+                seed = value.parse::<u64>().map_err(|_| format!("invalid seed: {}", value))?;
+            }
+            _ => game_args.push(arg.clone()),
+        }
+    }
+
+    if let Some(path) = replay {
+        if !game_args.is_empty() {
+            return Err("--replay can only be combined with --ticks".to_string());
+        }
+        let replay = Replay::load(&path).map_err(|e| e.to_string())?;
+        let mut player = ReplayPlayer::new(replay).map_err(|e| e.to_string())?;
+        print!("{}", headless::run_replay(&mut player, max_ticks));
+        return Ok(());
+    }
+
+    let game_args = game_args_from_args(&game_args, config.view.size)?;
+    let mut controller =
+        GameboardController::with_campaign(game_args.campaign, config.view.size, config.physics.clone(), game_args.mode);
+    controller.ai = Some(Ai::new(config.ai.clone(), seed));
+    if game_args.record.is_some() {
+        controller.recorder = Some(Recorder::new(&controller));
+    }
+
+    print!("{}", headless::run(&mut controller, max_ticks.unwrap_or(headless::DEFAULT_MAX_TICKS)));
+
+    if let (Some(path), Some(recorder)) = (game_args.record, controller.recorder) {
+        recorder.replay.save(&path).map_err(|e| e.to_string())?;
+        println!("Saved replay to {}", path.display());
+    }
+    Ok(())
+}
+
+/// Lets a bot play over JSON lines, on stdin and stdout or on the Unix socket given with `--socket <path>` (Unix only).
+/// `--lockstep` waits for the bot every tick, `--ticks <n>` stops early and the other options are those of a regular game.
+/// Everything meant for people goes to stderr, stdout belongs to the bot.
+fn run_bot(args: &[String], config: &Config) -> Result<(), String> {
+    let mut max_ticks = None;
+    let mut socket = None;
+    let mut lockstep = false;
+    let mut game_args = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--ticks" => {
+                let ticks = iter.next().ok_or("--ticks expects a number")?;
+                max_ticks = Some(ticks.parse::<u64>().map_err(|_| format!("invalid tick count: {}", ticks))?);
+            }
+            "--socket" => socket = Some(PathBuf::from(iter.next().ok_or("--socket expects a path")?)),
+            "--lockstep" => lockstep = true,
+            _ => game_args.push(arg.clone()),
+        }
+    }
+
+    let game_args = game_args_from_args(&game_args, config.view.size)?;
+    if game_args.ai {
+        return Err("--ai can not be combined with bot, the bot plays the first paddle".to_string());
+    }
+    let mut controller =
+        GameboardController::with_campaign(game_args.campaign, config.view.size, config.physics.clone(), game_args.mode);
+    // Bot games stay out of the high score table, which also keeps the results off stdout
+    controller.score_recorded = true;
+    if game_args.record.is_some() {
+        controller.recorder = Some(Recorder::new(&controller));
+    }
+
+    let (summary, controller) = match socket {
+        #[cfg(unix)]
+        Some(path) => {
+            let listener = UnixListener::bind(&path).map_err(|e| format!("could not listen on {}: {}", path.display(), e))?;
+            eprintln!("Waiting for a bot on {}", path.display());
+            let accepted = listener.accept().map_err(|e| e.to_string());
+            // The socket file is only needed to connect
+            let _ = fs::remove_file(&path);
+            let (stream, _) = accepted?;
+            let reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+            let mut session = BotSession::new(controller, reader, stream, lockstep);
+            (session.run(max_ticks), session.controller)
+        }
+        #[cfg(not(unix))]
+        Some(_) => return Err("--socket needs Unix domain sockets, use stdin and stdout on this platform".to_string()),
+        None => {
+            let mut session = BotSession::new(controller, BufReader::new(io::stdin()), io::stdout(), lockstep);
+            (session.run(max_ticks), session.controller)
+        }
+    };
+    eprint!("{}", summary.map_err(|e| e.to_string())?);
+
+    if let (Some(path), Some(recorder)) = (game_args.record, controller.recorder) {
+        recorder.replay.save(&path).map_err(|e| e.to_string())?;
+        eprintln!("Saved replay to {}", path.display());
+    }
+    Ok(())
+}
+
+/// Plays episodes of the learning environment in parallel with the ball following baseline and prints their returns,
+/// to check the environment works before training on it. `--threads <n>` defaults to one per core.
+fn run_gym(args: &[String]) -> Result<(), String> {
+    let number = |value: Option<&String>, name: &str| {
+        let value = value.ok_or(format!("{} expects a number", name))?;
+        value.parse::<u64>().map_err(|_| format!("invalid number: {}", value))
+    };
+    let mut seed = 0;
+    let mut episodes = 1;
+    let mut threads = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--seed" => seed = number(iter.next(), "--seed")?,
+            "--episodes" => episodes = number(iter.next(), "--episodes")?,
+            "--threads" => threads = Some(number(iter.next(), "--threads")? as usize),
+            other => return Err(format!("unknown argument: {}", other)),
+        }
+    }
+
+    let settings = EnvSettings::default();
+    let width = (settings.physics.paddle_size.x / settings.size) as f32;
+    let seeds: Vec<u64> = (seed..seed + episodes).collect();
+    let mut batch = BatchEnv::new(&settings, seeds.len(), threads);
+    batch.reset(&seeds);
+
+    let mut totals = vec![0.; seeds.len()];
+    let mut steps = vec![0; seeds.len()];
+    loop {
+        let actions: Vec<_> = batch.features().iter().map(|f| gym::follow_ball(f, width)).collect();
+        let results = batch.step(&actions);
+        for (i, (_, reward, done)) in results.iter().enumerate() {
+            totals[i] += reward;
+            if !done {
+                steps[i] += 1;
+            }
+        }
+        if results.iter().all(|r| r.2) {
+            break;
+        }
+    }
+
+    for (i, seed) in seeds.iter().enumerate() {
+        println!("Seed {}: return {:.1} after {} steps", seed, totals[i], steps[i] + 1);
+    }
+    Ok(())
+}
+
+/// Plays back a recorded session in the replay viewer.
+fn run_replay(path: &Path, view_settings: GameboardViewSettings) -> Result<(), String> {
+    let replay = Replay::load(path).map_err(|e| e.to_string())?;
+    let player = ReplayPlayer::new(replay).map_err(|e| e.to_string())?;
+
+    let (mut window, mut gl) = open_window(view_settings.window_size);
+    let mut events = Events::new(EventSettings::new());
+
+    let gameboard_view_settings = view_settings;
+    let mut viewer = ReplayViewer::new(player, gameboard_view_settings.position, gameboard_view_settings.size);
+    let gameboard_view = GameboardView::new(gameboard_view_settings);
+
+    while let Some(e) = events.next(&mut window) {
+        if let Some(args) = e.render_args() {
+            gl.draw(args.viewport(), |c, g| {
+                use graphics::clear;
+
+                clear([1.0; 4], g);
+                gameboard_view.draw_replay(&viewer, &c, g);
+            });
+        } else {
+            viewer.event(&e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Edits the level at `path`, starting from an empty level if the file does not exist yet.
+fn run_editor(path: PathBuf, view_settings: GameboardViewSettings, physics: PhysicsSettings) {
+    let level = if path.exists() {
+        Level::load(&path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        })
+    } else {
+        Level {
+            name: path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned()),
+            blocks: Vec::new(),
+        }
+    };
+
+    let (mut window, mut gl) = open_window(view_settings.window_size);
+    let mut events = Events::new(EventSettings::new());
+
+    let gameboard_view_settings = view_settings;
+    let mut editor_controller = EditorController::new(
+        Editor::new(level, gameboard_view_settings.size),
+        path,
+        gameboard_view_settings.position,
+        physics,
+    );
+    let gameboard_view = GameboardView::new(gameboard_view_settings);
+
+    while let Some(e) = events.next(&mut window) {
+        if let Some(args) = e.render_args() {
+            gl.draw(args.viewport(), |c, g| {
+                use graphics::clear;
+
+                clear([1.0; 4], g);
+                gameboard_view.draw_editor(&editor_controller, &c, g);
+            });
+        } else {
+            editor_controller.event(&e);
+        }
+    }
+}
+
+/// Reports every problem in the given level files, exiting with an error if there were any.
+/// `--coop side|stacked` or `--versus` check the levels for that mode instead of single player.
+fn run_validate(args: &[String], size: f64, physics: &PhysicsSettings) {
+    let mut failed = false;
+    let mut mode = Mode::Single;
+    let mut paths = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--coop" => {
+                mode = match iter.next().map(String::as_str) {
+                    Some("side") => Mode::Coop(CoopLayout::SideBySide),
+                    Some("stacked") => Mode::Coop(CoopLayout::Stacked),
+                    _ => exit_with_usage(),
+                }
+            }
+            "--versus" => mode = Mode::Versus,
+            path => paths.push(path),
+        }
+    }
+    if paths.is_empty() {
+        exit_with_usage();
+    }
+
+    for path in paths {
+        let level = match Level::load(Path::new(path)) {
+            Ok(level) => level,
+            Err(e) => {
+                println!("{}", e);
+                failed = true;
+                continue;
+            }
+        };
+
+        for problem in validator::validate(&level, size, physics, mode).iter() {
+            println!("{}: {}", path, validator::Located { level: &level, problem });
+            failed = true;
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// Converts an LBreakout2 level file into a level pack.
+fn run_import(source: &str, manifest: &str, size: f64) -> Result<(), String> {
+    let text = fs::read_to_string(source).map_err(|e| format!("could not read {}: {}", source, e))?;
+    let levels = lbreakout::import(&text, size).map_err(|e| format!("{}: {}", source, e))?;
+
+    let manifest = Path::new(manifest);
+    let pack = LevelPack {
+        name: manifest.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned()),
+        levels,
+    };
+    pack.save(manifest).map_err(|e| e.to_string())?;
+
+    println!("Imported {} levels into {}", pack.levels.len(), manifest.display());
+    Ok(())
+}
+
+/// Prints the level code of each level file.
+fn run_code(paths: &[String]) -> Result<(), String> {
+    for path in paths {
+        let level = Level::load(Path::new(path)).map_err(|e| e.to_string())?;
+        let code = level_code::encode(&level).map_err(|e| format!("{}: {}", path, e))?;
+        println!("{}: {}", path, code);
+    }
+
+    Ok(())
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("usage: clgame [--pack <manifest> | --showcase | --seed <n> | --code <level code>] [--level <n>] [--coop side|stacked | --versus] [--ai] [--record <replay file>]");
+    eprintln!("       clgame --load <save file>");
+    eprintln!("       clgame edit <level file>");
+    eprintln!("       clgame validate [--coop side|stacked | --versus] <level file>...");
+    eprintln!("       clgame import <lbreakout2 file> <manifest>");
+    eprintln!("       clgame code <level file>...");
+    eprintln!("       clgame scores");
+    eprintln!("       clgame replay <replay file>");
+    eprintln!("       clgame headless [--ticks <n>] [--replay <replay file> | --ai-seed <n> <game options>]");
+    eprintln!("       clgame bot [--socket <path>] [--lockstep] [--ticks <n>] <game options>");
+    eprintln!("       clgame gym [--seed <n>] [--episodes <n>] [--threads <n>]");
+    eprintln!("       clgame config");
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let config = Config::load(Path::new(config::CONFIG_PATH)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let size = config.view.size;
+
+    match args.first().map(String::as_str) {
+        Some("edit") => match args.get(1) {
+            Some(path) => run_editor(PathBuf::from(path), config.view.clone(), config.physics.clone()),
+            None => exit_with_usage(),
+        },
+        Some("validate") if args.len() > 1 => run_validate(&args[1..], size, &config.physics),
+        Some("import") if args.len() == 3 => {
+            if let Err(e) = run_import(&args[1], &args[2], size) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some("code") if args.len() > 1 => {
+            if let Err(e) = run_code(&args[1..]) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some("scores") => {
+            if let Some(table) = load_high_scores() {
+                print!("{}", table);
+            }
+        }
+        Some("replay") if args.len() == 2 => {
+            if let Err(e) = run_replay(Path::new(&args[1]), config.view.clone()) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some("--load") if args.len() == 2 => match save::load(Path::new(&args[1])) {
+            Ok(controller) => run_game(controller, None, &config, None),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        },
+        Some("headless") => {
+            if let Err(e) = run_headless(&args[1..], &config) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some("bot") => {
+            if let Err(e) = run_bot(&args[1..], &config) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some("gym") => {
+            if let Err(e) = run_gym(&args[1..]) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some("config") => print!("{}", Config::default_toml()),
+        _ => {
+            let game_args = game_args_from_args(&args, size).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                exit_with_usage();
+            });
+            let mut controller =
+                GameboardController::with_campaign(game_args.campaign, size, config.physics.clone(), game_args.mode);
+            if game_args.ai {
+                let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+                controller.ai = Some(Ai::new(config.ai.clone(), seed));
+            }
+            run_game(controller, game_args.record, &config, game_args.pack_path);
         }
     }
 }
@@ -1,5 +1,7 @@
 use std::{ f64, ops };
 
+use serde::{Deserialize, Serialize};
+
 static CMP_EPSILON : f64 = 0.000000000000001f64;
 
 pub fn clamp<T: PartialOrd>(val: T, low: T, high: T) -> T {
@@ -12,7 +14,84 @@ pub fn clamp<T: PartialOrd>(val: T, low: T, high: T) -> T {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+/// Small deterministic pseudo random number generator (SplitMix64).
+/// Unlike external crates its output is guaranteed to never change, which keeps seeded content stable.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0; 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Uniform in [low; high)
+    pub fn range(&mut self, low: u32, high: u32) -> u32 {
+        if high <= low {
+            return low;
+        }
+        low + (self.next_u64() % u64::from(high - low)) as u32
+    }
+
+    pub fn chance(&mut self, probability: f64) -> bool {
+        self.next_f64() < probability
+    }
+}
+
+/// 64 bit FNV-1a hash. Unlike `std::hash::DefaultHasher` its output is fixed,
+/// so hashes can be stored in files and compared across builds.
+pub struct StateHasher {
+    state: u64,
+}
+
+impl StateHasher {
+    pub fn new() -> Self {
+        Self { state: 0xCBF2_9CE4_8422_2325 }
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        for byte in value.to_le_bytes() {
+            self.state ^= u64::from(byte);
+            self.state = self.state.wrapping_mul(0x0100_0000_01B3);
+        }
+    }
+
+    // Hashes the exact bits, so even the smallest rounding difference shows up
+    pub fn write_f64(&mut self, value: f64) {
+        self.write_u64(value.to_bits());
+    }
+
+    pub fn write_vec2(&mut self, value: &Vec2) {
+        self.write_f64(value.x);
+        self.write_f64(value.y);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
 pub struct Vec2 {
     pub x: f64,
     pub y: f64,
@@ -395,4 +474,27 @@ mod tests {
 
         assert!(input.reflect_on(&mirror).equalish(&expected));
     }
+
+    #[test]
+    fn test_rng_is_deterministic() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+    }
+
+    #[test]
+    fn test_rng_range_bounds() {
+        let mut rng = Rng::new(7);
+
+        for _ in 0..1000 {
+            let v = rng.range(3, 9);
+            assert!((3..9).contains(&v));
+            let f = rng.next_f64();
+            assert!((0. ..1.).contains(&f));
+        }
+    }
 }
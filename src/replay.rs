@@ -0,0 +1,284 @@
+//! Input recording and deterministic replay.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::actions::Action;
+use crate::gameboard_controller::{GameboardController, Snapshot};
+use crate::save::{self, SaveError};
+
+/// Bumped whenever the replay file changes shape.
+pub const REPLAY_VERSION: u32 = 4;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, serde_json::Error),
+    UnsupportedVersion(u32),
+    /// The recorded starting state could not be restored.
+    Start(SaveError),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(path, e) => write!(f, "could not access {}: {}", path.display(), e),
+            ReplayError::Parse(path, e) => write!(f, "replay {} is damaged: {}", path.display(), e),
+            ReplayError::UnsupportedVersion(v) => {
+                write!(f, "replay version {} is not supported, this game reads version {}", v, REPLAY_VERSION)
+            }
+            ReplayError::Start(e) => write!(f, "replay start state: {}", e),
+        }
+    }
+}
+
+/// A change to what the player does.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Action { action: Action, pressed: bool },
+    /// Board x coordinate player one's paddle follows, from the mouse, the AI or a bot.
+    PaddleTarget(f64),
+}
+
+/// An input applied right before the simulation tick `tick` runs.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct InputEvent {
+    pub tick: u64,
+    pub input: Input,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Replay {
+    pub version: u32,
+    /// Time step of every tick.
+    pub dt: f64,
+    /// Number of ticks recorded.
+    pub ticks: u64,
+    /// Save state the recording started from.
+    pub start: String,
+    pub inputs: Vec<InputEvent>,
+    /// `Gameboard::state_hash` after every tick, replays without them are played unchecked.
+    #[serde(default)]
+    pub hashes: Vec<u64>,
+}
+
+impl Replay {
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        let text = serde_json::to_string(self).expect("Replays are always serializable");
+        fs::write(path, text).map_err(|e| ReplayError::Io(path.to_path_buf(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let text = fs::read_to_string(path).map_err(|e| ReplayError::Io(path.to_path_buf(), e))?;
+        let replay: Replay = serde_json::from_str(&text).map_err(|e| ReplayError::Parse(path.to_path_buf(), e))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(replay.version));
+        }
+        Ok(replay)
+    }
+
+    /// Restores the state the recording started from.
+    pub fn start_controller(&self) -> Result<GameboardController, ReplayError> {
+        save::from_str(&self.start).map_err(ReplayError::Start)
+    }
+}
+
+/// Collects the inputs of a running game.
+pub struct Recorder {
+    pub replay: Replay,
+}
+
+impl Recorder {
+    /// Starts recording from the current state of `controller`.
+    pub fn new(controller: &GameboardController) -> Self {
+        Self {
+            replay: Replay {
+                version: REPLAY_VERSION,
+                dt: 0.,
+                ticks: 0,
+                start: save::to_string(controller),
+                inputs: Vec::new(),
+                hashes: Vec::new(),
+            },
+        }
+    }
+
+    pub fn record(&mut self, tick: u64, input: Input) {
+        self.replay.inputs.push(InputEvent { tick, input });
+    }
+
+    /// Records a finished tick and the state hash it ended with.
+    pub fn record_tick(&mut self, dt: f64, hash: u64) {
+        // The event loop runs at a fixed update rate, so the first time step holds for all ticks
+        if self.replay.ticks == 0 {
+            self.replay.dt = dt;
+        }
+        self.replay.ticks += 1;
+        self.replay.hashes.push(hash);
+    }
+}
+
+/// Ticks between the snapshots a player keeps for seeking.
+pub const SNAPSHOT_INTERVAL: u64 = 120;
+
+/// Runs a replay tick by tick.
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    pub controller: GameboardController,
+    next_input: usize,
+    /// State every `SNAPSHOT_INTERVAL` ticks with the index of the next input, taken while playing.
+    snapshots: Vec<(Snapshot, usize)>,
+    /// First tick whose state hash did not match the recording.
+    divergence: Option<u64>,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Result<Self, ReplayError> {
+        let controller = replay.start_controller()?;
+        Ok(Self {
+            snapshots: vec![(controller.snapshot(), 0)],
+            controller,
+            replay,
+            next_input: 0,
+            divergence: None,
+        })
+    }
+
+    /// Jumps to the given tick, restoring the closest earlier snapshot and simulating from there.
+    pub fn seek(&mut self, tick: u64) {
+        let tick = tick.min(self.replay.ticks);
+        let index = ((tick / SNAPSHOT_INTERVAL) as usize).min(self.snapshots.len() - 1);
+        let snapshot_tick = index as u64 * SNAPSHOT_INTERVAL;
+
+        // Running forward is cheaper if we are already between the snapshot and the target
+        if self.controller.tick < snapshot_tick || self.controller.tick > tick {
+            let (ref snapshot, next_input) = self.snapshots[index];
+            self.controller.restore(snapshot);
+            self.next_input = next_input;
+        }
+
+        while self.controller.tick < tick {
+            self.step();
+        }
+    }
+
+    /// The earliest tick found so far after which the simulation no longer matches the recording.
+    pub fn divergence(&self) -> Option<u64> {
+        self.divergence
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.controller.tick >= self.replay.ticks
+    }
+
+    /// Applies the inputs of the next tick and runs it, returns false once the replay is over.
+    pub fn step(&mut self) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+
+        let tick = self.controller.tick;
+        while let Some(input) = self.replay.inputs.get(self.next_input) {
+            if input.tick > tick {
+                break;
+            }
+            self.controller.handle_input(input.input);
+            self.next_input += 1;
+        }
+
+        self.controller.step(self.replay.dt);
+
+        let tick = self.controller.tick;
+        if let Some(&hash) = self.replay.hashes.get(tick as usize - 1) {
+            if hash != self.controller.gameboard.state_hash() && self.divergence.is_none_or(|t| tick < t) {
+                self.divergence = Some(tick);
+            }
+        }
+        if tick.is_multiple_of(SNAPSHOT_INTERVAL) && self.snapshots.len() as u64 == tick / SNAPSHOT_INTERVAL {
+            self.snapshots.push((self.controller.snapshot(), self.next_input));
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboard::Gameboard;
+
+    #[test]
+    fn test_replay_reproduces_game() {
+        let mut game = GameboardController::new(Gameboard::new(400.));
+        game.recorder = Some(Recorder::new(&game));
+
+        for i in 0..3000 {
+            match i % 200 {
+                0 => game.handle_action(Action::MoveLeft, true),
+                70 => game.handle_action(Action::MoveRight, true),
+                90 => game.handle_action(Action::MoveLeft, false),
+                150 => game.handle_action(Action::MoveRight, false),
+                _ => (),
+            }
+            // Actions outside the simulation are not recorded
+            game.handle_action(Action::QuickSave, true);
+            if i % 500 == 250 {
+                game.handle_input(Input::PaddleTarget(i as f64 / 10.));
+            }
+            game.step(1. / 120.);
+        }
+
+        let replay = game.recorder.take().unwrap().replay;
+        assert_eq!(replay.inputs.len(), 66);
+
+        let mut player = ReplayPlayer::new(replay).unwrap();
+        while player.step() {}
+
+        assert_eq!(player.controller.tick, 3000);
+        assert_eq!(save::to_string(&player.controller), save::to_string(&game));
+
+        // Seeking backwards and forwards ends up in the same state
+        player.seek(1234);
+        let at_1234 = save::to_string(&player.controller);
+        player.seek(10);
+        player.seek(1234);
+        assert_eq!(save::to_string(&player.controller), at_1234);
+        player.seek(3000);
+        assert_eq!(save::to_string(&player.controller), save::to_string(&game));
+        assert_eq!(player.divergence(), None);
+    }
+
+    #[test]
+    fn test_divergence_is_detected() {
+        let mut game = GameboardController::new(Gameboard::new(400.));
+        game.recorder = Some(Recorder::new(&game));
+        for _ in 0..600 {
+            game.step(1. / 120.);
+        }
+
+        let mut replay = game.recorder.take().unwrap().replay;
+        assert_eq!(replay.hashes.len(), 600);
+        // Pretend the physics changed after the recording was made
+        replay.hashes[455] ^= 1;
+
+        let mut player = ReplayPlayer::new(replay).unwrap();
+        while player.step() {}
+        assert_eq!(player.divergence(), Some(456));
+    }
+
+    #[test]
+    fn test_state_hash_covers_state() {
+        let board = Gameboard::new(400.);
+        let mut moved = board.clone();
+        moved.ball.body.velocity.x += 1e-9;
+        let mut hit = board.clone();
+        hit.blocks.pop();
+
+        assert_eq!(board.state_hash(), Gameboard::new(400.).state_hash());
+        assert_ne!(board.state_hash(), moved.state_hash());
+        assert_ne!(board.state_hash(), hit.state_hash());
+    }
+}
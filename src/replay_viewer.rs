@@ -0,0 +1,133 @@
+//! Replay viewer with pausing, stepping, playback speed and seeking.
+
+use piston::input::GenericEvent;
+
+use crate::replay::ReplayPlayer;
+
+const MIN_SPEED: f64 = 0.125;
+const MAX_SPEED: f64 = 16.;
+
+/// Ticks skipped by a single seek key press.
+const SEEK_STEP: u64 = 600;
+
+/// Gap between the board and the timeline below it.
+pub const TIMELINE_MARGIN: f64 = 10.;
+pub const TIMELINE_HEIGHT: f64 = 12.;
+
+/// Handles events while watching a replay.
+pub struct ReplayViewer {
+    pub player: ReplayPlayer,
+    pub paused: bool,
+    /// Ticks simulated per update event.
+    pub speed: f64,
+    /// Fractional ticks carried over between updates when not running at whole speeds.
+    budget: f64,
+    /// Top left corner of the board in window coordinates.
+    pub board_position: [f64; 2],
+    pub board_size: f64,
+    cursor: [f64; 2],
+    /// Set once a divergence from the recording has been reported.
+    reported_divergence: bool,
+}
+
+impl ReplayViewer {
+    pub fn new(player: ReplayPlayer, board_position: [f64; 2], board_size: f64) -> Self {
+        Self {
+            player,
+            paused: false,
+            speed: 1.,
+            budget: 0.,
+            board_position,
+            board_size,
+            cursor: [0.; 2],
+            reported_divergence: false,
+        }
+    }
+
+    /// Window rectangle of the timeline below the board.
+    pub fn timeline_rect(&self) -> [f64; 4] {
+        [
+            self.board_position[0],
+            self.board_position[1] + self.board_size + TIMELINE_MARGIN,
+            self.board_size,
+            TIMELINE_HEIGHT,
+        ]
+    }
+
+    /// Fraction of the replay watched so far.
+    pub fn progress(&self) -> f64 {
+        if self.player.replay.ticks == 0 {
+            return 1.;
+        }
+        self.player.controller.tick as f64 / self.player.replay.ticks as f64
+    }
+
+    /// Position of the first diverging tick on the timeline, as a fraction of the replay.
+    pub fn divergence(&self) -> Option<f64> {
+        let tick = self.player.divergence()?;
+        Some(tick as f64 / self.player.replay.ticks.max(1) as f64)
+    }
+
+    fn seek_to_cursor(&mut self) {
+        let [x, y, w, h] = self.timeline_rect();
+        let [cx, cy] = self.cursor;
+        if cx >= x && cx <= x + w && cy >= y && cy <= y + h {
+            let fraction = (cx - x) / w;
+            self.player.seek((fraction * self.player.replay.ticks as f64).round() as u64);
+        }
+    }
+
+    fn advance(&mut self) {
+        if self.paused {
+            return;
+        }
+
+        self.budget += self.speed;
+        while self.budget >= 1. {
+            self.budget -= 1.;
+            if !self.player.step() {
+                self.budget = 0.;
+                break;
+            }
+        }
+    }
+
+    /// Handles events.
+    pub fn event<E: GenericEvent>(&mut self, e: &E) {
+        use piston::input::{Button, ButtonState, Key, MouseButton};
+
+        if let Some([x, y]) = e.mouse_cursor_args() {
+            self.cursor = [x, y];
+        }
+
+        if let Some(button_args) = e.button_args() {
+            if button_args.state == ButtonState::Press {
+                let tick = self.player.controller.tick;
+                match button_args.button {
+                    Button::Keyboard(Key::Space) => self.paused = !self.paused,
+                    Button::Keyboard(Key::Period) if self.paused => {
+                        self.player.step();
+                    }
+                    Button::Keyboard(Key::Comma) if self.paused => self.player.seek(tick.saturating_sub(1)),
+                    Button::Keyboard(Key::Up) => self.speed = (self.speed * 2.).min(MAX_SPEED),
+                    Button::Keyboard(Key::Down) => self.speed = (self.speed / 2.).max(MIN_SPEED),
+                    Button::Keyboard(Key::Left) => self.player.seek(tick.saturating_sub(SEEK_STEP)),
+                    Button::Keyboard(Key::Right) => self.player.seek(tick + SEEK_STEP),
+                    Button::Keyboard(Key::Home) => self.player.seek(0),
+                    Button::Keyboard(Key::End) => self.player.seek(self.player.replay.ticks),
+                    Button::Mouse(MouseButton::Left) => self.seek_to_cursor(),
+                    _ => (),
+                }
+            }
+        }
+
+        if e.update_args().is_some() {
+            self.advance();
+        }
+
+        if let (false, Some(tick)) = (self.reported_divergence, self.player.divergence()) {
+            self.reported_divergence = true;
+            println!("The simulation diverges from the recording at tick {}", tick);
+        }
+    }
+}
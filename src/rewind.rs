@@ -0,0 +1,209 @@
+//! Rewinding the running game with a ring buffer of snapshots.
+
+use std::collections::VecDeque;
+
+use crate::actions::Action;
+use crate::gameboard_controller::{GameboardController, Snapshot};
+use crate::replay::{Input, InputEvent};
+
+/// Ticks between two snapshots.
+pub const SNAPSHOT_INTERVAL: u64 = 30;
+/// Snapshots kept, at 120 updates per second this covers ten seconds.
+pub const CAPACITY: usize = 40;
+/// Ticks rewound per update while the rewind key is held.
+pub const REWIND_SPEED: u64 = 2;
+
+/// Move left and move right actions of each player.
+const MOVES: [(Action, Action); 2] = [(Action::MoveLeft, Action::MoveRight), (Action::P2MoveLeft, Action::P2MoveRight)];
+
+/// A snapshot together with the inputs made until the next one.
+struct Entry {
+    snapshot: Snapshot,
+    inputs: Vec<InputEvent>,
+}
+
+pub struct RewindBuffer {
+    entries: VecDeque<Entry>,
+    /// Time step used to simulate forward from a snapshot.
+    dt: f64,
+}
+
+impl RewindBuffer {
+    /// Starts buffering from the current state of `controller`.
+    pub fn new(controller: &GameboardController) -> Self {
+        let mut entries = VecDeque::with_capacity(CAPACITY);
+        entries.push_back(Entry {
+            snapshot: controller.snapshot(),
+            inputs: Vec::new(),
+        });
+        Self { entries, dt: 0. }
+    }
+
+    /// Oldest tick the game can be rewound to.
+    pub fn oldest_tick(&self) -> u64 {
+        self.entries.front().map_or(0, |e| e.snapshot.tick)
+    }
+
+    pub fn record_input(&mut self, tick: u64, input: Input) {
+        if let Some(entry) = self.entries.back_mut() {
+            entry.inputs.push(InputEvent { tick, input });
+        }
+    }
+
+    /// Called after every tick, takes a snapshot every `SNAPSHOT_INTERVAL` ticks.
+    pub fn record_tick(&mut self, controller: &GameboardController, dt: f64) {
+        self.dt = dt;
+        if !controller.tick.is_multiple_of(SNAPSHOT_INTERVAL) {
+            return;
+        }
+
+        if self.entries.len() == CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry {
+            snapshot: controller.snapshot(),
+            inputs: Vec::new(),
+        });
+    }
+
+    /// Moves `controller` back by up to `ticks` ticks and forgets everything after that point,
+    /// so the game resumes from there. Returns false once nothing is left to rewind.
+    pub fn rewind(&mut self, controller: &mut GameboardController, ticks: u64) -> bool {
+        let target = controller.tick.saturating_sub(ticks).max(self.oldest_tick());
+        if target == controller.tick {
+            return false;
+        }
+
+        while self.entries.len() > 1 && self.entries.back().is_some_and(|e| e.snapshot.tick > target) {
+            self.entries.pop_back();
+        }
+        let entry = self.entries.back_mut().expect("the buffer always keeps one snapshot");
+        entry.inputs.retain(|input| input.tick < target);
+
+        let held: Vec<(bool, bool)> = controller.gameboard.players.iter().map(|p| (p.left_held, p.right_held)).collect();
+        controller.restore(&entry.snapshot);
+        let mut inputs = entry.inputs.iter().peekable();
+        while controller.tick < target {
+            while let Some(input) = inputs.next_if(|input| input.tick <= controller.tick) {
+                controller.handle_input(input.input);
+            }
+            controller.step(self.dt);
+        }
+
+        // The snapshot brings back the keys held at that time, the game goes on with the ones held now.
+        // Releasing first keeps the other key from taking over after the press.
+        for pressed in [false, true] {
+            for (index, &(left, right)) in held.iter().enumerate() {
+                let player = &controller.gameboard.players[index];
+                let (restored_left, restored_right) = (player.left_held, player.right_held);
+                let (left_action, right_action) = MOVES[index];
+                if left == pressed && restored_left != left {
+                    controller.handle_action(left_action, left);
+                }
+                if right == pressed && restored_right != right {
+                    controller.handle_action(right_action, right);
+                }
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboard::{Direction, Gameboard};
+    use crate::highscore::HighScores;
+    use crate::save;
+
+    fn play(controller: &mut GameboardController, ticks: u64) {
+        for _ in 0..ticks {
+            match controller.tick % 100 {
+                0 => controller.handle_action(Action::MoveLeft, true),
+                40 => controller.handle_action(Action::MoveLeft, false),
+                _ => (),
+            }
+            controller.step(1. / 120.);
+        }
+    }
+
+    #[test]
+    fn test_rewind_restores_earlier_state() {
+        let mut controller = GameboardController::new(Gameboard::new(400.));
+        controller.rewind = Some(RewindBuffer::new(&controller));
+
+        play(&mut controller, 437);
+        let at_437 = save::to_string(&controller);
+        play(&mut controller, 100);
+
+        let mut buffer = controller.rewind.take().unwrap();
+        for _ in 0..50 {
+            assert!(buffer.rewind(&mut controller, REWIND_SPEED));
+        }
+        controller.rewind = Some(buffer);
+
+        assert_eq!(controller.tick, 437);
+        assert_eq!(save::to_string(&controller), at_437);
+    }
+
+    #[test]
+    fn test_rewind_is_limited_to_buffer() {
+        let mut controller = GameboardController::new(Gameboard::new(400.));
+        controller.rewind = Some(RewindBuffer::new(&controller));
+
+        play(&mut controller, SNAPSHOT_INTERVAL * CAPACITY as u64 + 45);
+
+        let mut buffer = controller.rewind.take().unwrap();
+        while buffer.rewind(&mut controller, 1000) {}
+
+        assert_eq!(controller.tick, SNAPSHOT_INTERVAL * 2);
+    }
+
+    #[test]
+    fn test_rewind_keeps_keys_held_now() {
+        let mut controller = GameboardController::new(Gameboard::new(400.));
+        controller.rewind = Some(RewindBuffer::new(&controller));
+        let player = |c: &GameboardController| (c.gameboard.players[0].left_held, c.gameboard.players[0].direction);
+
+        // Held at tick 120 but released at 140
+        play(&mut controller, 150);
+        let mut buffer = controller.rewind.take().unwrap();
+        buffer.rewind(&mut controller, 30);
+        controller.rewind = Some(buffer);
+        assert!(player(&controller) == (false, Direction::Idle));
+
+        // Released at tick 180 but pressed again at 200
+        play(&mut controller, 90);
+        let mut buffer = controller.rewind.take().unwrap();
+        buffer.rewind(&mut controller, 30);
+        assert!(player(&controller) == (true, Direction::Left));
+    }
+
+    #[test]
+    fn test_score_is_recorded_once() {
+        let path = std::env::temp_dir().join(format!("rustout_rewind_highscores_{}.json", std::process::id()));
+        let mut controller = GameboardController::new(Gameboard::new(400.));
+        controller.high_scores = Some(HighScores::load(&path).unwrap());
+        controller.rewind = Some(RewindBuffer::new(&controller));
+        controller.gameboard.lives = 1;
+        // Out of the falling ball's way
+        controller.gameboard.players[0].body.hitbox.position.x = 290.;
+
+        let lose = |controller: &mut GameboardController| {
+            while !controller.is_game_finished() {
+                controller.step(1. / 120.);
+            }
+        };
+        lose(&mut controller);
+        let mut buffer = controller.rewind.take().unwrap();
+        buffer.rewind(&mut controller, 40);
+        controller.rewind = Some(buffer);
+        assert!(!controller.is_game_finished());
+        lose(&mut controller);
+
+        let entries = controller.high_scores.as_ref().unwrap().entries().len();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries, 1);
+    }
+}
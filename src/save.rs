@@ -0,0 +1,134 @@
+//! Saving and loading games in progress.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::gameboard::Gameboard;
+use crate::gameboard_controller::GameboardController;
+use crate::level::Campaign;
+
+/// Bumped whenever the saved state changes shape.
+pub const SAVE_VERSION: u32 = 2;
+
+/// Where the quick save keys store the game.
+pub const QUICK_SAVE_PATH: &str = "quicksave.json";
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(PathBuf, io::Error),
+    Parse(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(path, e) => write!(f, "could not access {}: {}", path.display(), e),
+            SaveError::Parse(e) => write!(f, "save file is damaged: {}", e),
+            SaveError::UnsupportedVersion(v) => {
+                write!(f, "save file version {} is not supported, this game reads version {}", v, SAVE_VERSION)
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SaveGame<'a> {
+    version: u32,
+    gameboard: &'a Gameboard,
+    campaign: &'a Option<Campaign>,
+}
+
+#[derive(Deserialize)]
+struct SaveVersion {
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct LoadedGame {
+    gameboard: Gameboard,
+    campaign: Option<Campaign>,
+}
+
+/// Serializes the board and campaign progress of `controller`.
+pub fn to_string(controller: &GameboardController) -> String {
+    let save = SaveGame {
+        version: SAVE_VERSION,
+        gameboard: &controller.gameboard,
+        campaign: &controller.campaign,
+    };
+    serde_json::to_string(&save).expect("Game state is always serializable")
+}
+
+pub fn from_str(text: &str) -> Result<GameboardController, SaveError> {
+    // Check the version first so old files give a clear error instead of a missing field
+    let SaveVersion { version } = serde_json::from_str(text).map_err(SaveError::Parse)?;
+    if version != SAVE_VERSION {
+        return Err(SaveError::UnsupportedVersion(version));
+    }
+
+    let loaded: LoadedGame = serde_json::from_str(text).map_err(SaveError::Parse)?;
+    let mut controller = GameboardController::new(loaded.gameboard);
+    controller.campaign = loaded.campaign;
+    Ok(controller)
+}
+
+pub fn save(controller: &GameboardController, path: &Path) -> Result<(), SaveError> {
+    fs::write(path, to_string(controller)).map_err(|e| SaveError::Io(path.to_path_buf(), e))
+}
+
+pub fn load(path: &Path) -> Result<GameboardController, SaveError> {
+    let text = fs::read_to_string(path).map_err(|e| SaveError::Io(path.to_path_buf(), e))?;
+    from_str(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboard::{Direction, PhysicsSettings};
+
+    fn run(controller: &mut GameboardController, ticks: u32) {
+        for i in 0..ticks {
+            controller.gameboard.players[0].direction = if i % 90 < 45 { Direction::Left } else { Direction::Right };
+            controller.gameboard.update(1. / 120.);
+        }
+    }
+
+    #[test]
+    fn test_resumed_game_is_identical() {
+        let mut original = GameboardController::new(Gameboard::new(400.));
+        run(&mut original, 500);
+
+        let mut resumed = from_str(&to_string(&original)).unwrap();
+        run(&mut original, 2000);
+        run(&mut resumed, 2000);
+
+        assert_eq!(to_string(&original), to_string(&resumed));
+    }
+
+    #[test]
+    fn test_round_trips_physics() {
+        let physics = PhysicsSettings {
+            mouse_max_speed: 1e9,
+            ..PhysicsSettings::default()
+        };
+        let controller = GameboardController::new(Gameboard::with_physics(400., Vec::new(), physics.clone()));
+
+        let loaded = from_str(&to_string(&controller)).unwrap();
+        assert_eq!(loaded.gameboard.physics, physics);
+    }
+
+    #[test]
+    fn test_rejects_other_versions() {
+        let text = to_string(&GameboardController::new(Gameboard::new(400.))).replacen("\"version\":2", "\"version\":99", 1);
+
+        match from_str(&text) {
+            Err(SaveError::UnsupportedVersion(99)) => (),
+            _ => panic!("expected an unsupported version error"),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Level validation.
+
+use std::fmt;
+
+use crate::gameboard::{BlockKind, Facing, Mode, PhysicsSettings};
+use crate::level::{BlockSpec, Level};
+
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// Two blocks share some area, touching edges are fine.
+    Overlap { first: usize, second: usize },
+    OutsideBoard { block: usize },
+    /// The block reaches into the area a paddle moves along.
+    InPaddleZone { block: usize },
+    NoDestructibleBlocks,
+}
+
+/// A problem together with the level it was found in, used for reporting.
+pub struct Located<'a> {
+    pub level: &'a Level,
+    pub problem: &'a Problem,
+}
+
+fn describe(level: &Level, block: usize) -> String {
+    let spec = &level.blocks[block];
+    format!("block {} at ({}, {})", block, spec.x, spec.y)
+}
+
+impl<'a> fmt::Display for Located<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = self.level;
+        match *self.problem {
+            Problem::Overlap { first, second } => {
+                write!(f, "{} overlaps {}", describe(level, first), describe(level, second))
+            }
+            Problem::OutsideBoard { block } => write!(f, "{} is outside the board", describe(level, block)),
+            Problem::InPaddleZone { block } => {
+                write!(f, "{} intersects the paddle's travel zone", describe(level, block))
+            }
+            Problem::NoDestructibleBlocks => write!(f, "level has no destructible blocks"),
+        }
+    }
+}
+
+fn overlaps(a: &BlockSpec, b: &BlockSpec) -> bool {
+    a.x < b.x + b.w && b.x < a.x + a.w && a.y < b.y + b.h && b.y < a.y + a.h
+}
+
+/// Returns every problem of `level` when played on a board of the given size with the given physics and mode.
+pub fn validate(level: &Level, size: f64, physics: &PhysicsSettings, mode: Mode) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let blocks = &level.blocks;
+
+    for (i, a) in blocks.iter().enumerate() {
+        for (j, b) in blocks.iter().enumerate().skip(i + 1) {
+            if overlaps(a, b) {
+                problems.push(Problem::Overlap { first: i, second: j });
+            }
+        }
+    }
+
+    for (i, spec) in blocks.iter().enumerate() {
+        if spec.x < 0. || spec.y < 0. || spec.x + spec.w > size || spec.y + spec.h > size {
+            problems.push(Problem::OutsideBoard { block: i });
+        }
+    }
+
+    // Checked on the board as it is played, versus mode moves the blocks to the middle
+    let board = level.make_board(size, physics.clone(), mode);
+    for (i, block) in board.blocks.iter().enumerate() {
+        let hitbox = &block.body.hitbox;
+        let in_zone = board.players.iter().any(|player| {
+            let paddle = &player.body.hitbox;
+            match player.facing {
+                Facing::Up => hitbox.position.y + hitbox.dimension.y > paddle.position.y,
+                Facing::Down => hitbox.position.y < paddle.position.y + paddle.dimension.y,
+            }
+        });
+        if in_zone {
+            problems.push(Problem::InPaddleZone { block: i });
+        }
+    }
+
+    if !blocks.iter().any(|spec| spec.kind != BlockKind::Indestructible) {
+        problems.push(Problem::NoDestructibleBlocks);
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboard::CoopLayout;
+    use crate::math::Vec2;
+    use crate::level::LevelPack;
+
+    fn spec(x: f64, y: f64, kind: BlockKind) -> BlockSpec {
+        BlockSpec { x, y, w: 40., h: 10., color: [0, 0, 0, 255], kind }
+    }
+
+    fn level(blocks: Vec<BlockSpec>) -> Level {
+        Level { name: String::new(), blocks }
+    }
+
+    #[test]
+    fn test_showcase_levels_are_valid() {
+        let modes = [Mode::Single, Mode::Coop(CoopLayout::SideBySide), Mode::Coop(CoopLayout::Stacked), Mode::Versus];
+        for mode in modes {
+            for level in LevelPack::showcase(400.).levels.iter() {
+                assert_eq!(validate(level, 400., &PhysicsSettings::default(), mode), Vec::new(), "{} {:?}", level.name, mode);
+            }
+        }
+    }
+
+    #[test]
+    fn test_touching_blocks_do_not_overlap() {
+        let touching = level(vec![spec(0., 0., BlockKind::Normal), spec(40., 0., BlockKind::Normal)]);
+        let overlapping = level(vec![spec(0., 0., BlockKind::Normal), spec(39., 5., BlockKind::Normal)]);
+
+        assert_eq!(validate(&touching, 400., &PhysicsSettings::default(), Mode::Single), Vec::new());
+        assert_eq!(validate(&overlapping, 400., &PhysicsSettings::default(), Mode::Single), vec![Problem::Overlap { first: 0, second: 1 }]);
+    }
+
+    #[test]
+    fn test_reports_all_problems() {
+        let problems = validate(
+            &level(vec![spec(-5., 0., BlockKind::Indestructible), spec(100., 345., BlockKind::Indestructible)]),
+            400.,
+            &PhysicsSettings::default(),
+            Mode::Single,
+        );
+
+        assert_eq!(
+            problems,
+            vec![
+                Problem::OutsideBoard { block: 0 },
+                Problem::InPaddleZone { block: 1 },
+                Problem::NoDestructibleBlocks,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_paddle_zone_follows_physics_and_mode() {
+        let low = level(vec![spec(100., 310., BlockKind::Normal)]);
+        let physics = PhysicsSettings::default();
+        assert_eq!(validate(&low, 400., &physics, Mode::Single), Vec::new());
+
+        // The second stacked paddle moves three paddle heights above the first
+        let problems = vec![Problem::InPaddleZone { block: 0 }];
+        assert_eq!(validate(&low, 400., &physics, Mode::Coop(CoopLayout::Stacked)), problems);
+
+        let raised = PhysicsSettings {
+            paddle_position: Vec2::new(150., 300.),
+            ..PhysicsSettings::default()
+        };
+        assert_eq!(validate(&low, 400., &raised, Mode::Single), problems);
+    }
+}